@@ -0,0 +1,256 @@
+//! Statistics gathered in the parse loop, each observing the entries left by
+//! the filters and reporting a section of its own.
+//!
+//! Every statistic of the report is an analyzer, the built in ones filling in
+//! their part of the report themselves. Those of `--analyze` are created from
+//! a [`Builtin`], other analyzers are registered with
+//! [`JournalStat::analyzer`](crate::JournalStat::analyzer).
+
+use crate::{
+    footprint,
+    report::{self, OutputFormat, Report},
+    source::Entry,
+    Error, Message,
+};
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+    any::Any,
+    collections::HashMap,
+    io::{self, Write},
+    str::FromStr,
+};
+
+/// A statistic gathered over the entries of a journal.
+///
+/// Analyzers are forked to count parts of the input separately and merged
+/// back together, so `merge` is only ever given a fork of the same analyzer.
+pub trait Analyzer: Any + Send {
+    /// Short name of the statistic, identifying its section in the JSON
+    /// report.
+    fn name(&self) -> &str;
+
+    /// Look at an entry that passed the filters, logged as `message`.
+    fn observe(&mut self, message: &Message, entry: &Entry);
+
+    /// An analyzer with the same settings that has observed nothing.
+    fn fork(&self) -> Box<dyn Analyzer>;
+
+    /// Add in the observations of `other`, a fork of this analyzer.
+    fn merge(&mut self, other: Box<dyn Analyzer>) -> crate::Result<()>;
+
+    /// Whether forks counting parts of the input can be merged into the
+    /// statistic of the whole input, rather than it depending on the order of
+    /// the entries.
+    fn mergeable(&self) -> bool {
+        true
+    }
+
+    /// Bring the statistic up to date with the entries observed so far, before
+    /// it is reported. Observing may continue afterwards.
+    fn complete(&mut self) {}
+
+    /// Sum up the entries observed so far into a report section. Called every
+    /// time the report is written, so observing may continue afterwards.
+    fn finish(&self) -> Section;
+
+    /// Add the statistic to the JSON report, by default as one of its
+    /// sections.
+    fn report<'a>(&'a self, document: &mut Report<'a>) {
+        document.sections.push(self.finish());
+    }
+
+    /// Write the statistic as tables, by default its section.
+    fn write_tables(&self, format: OutputFormat, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "{}", report::section(format, &self.finish()))
+    }
+}
+
+/// Recover the concrete type of an analyzer given to [`Analyzer::merge`].
+///
+/// # Errors
+///
+/// If `analyzer` is not an `A`, only forks being mergeable.
+pub fn downcast<A: Analyzer>(analyzer: Box<dyn Analyzer>) -> crate::Result<Box<A>> {
+    let name = analyzer.name().to_string();
+    (analyzer as Box<dyn Any>).downcast().map_err(|_| {
+        Error::Invalid(format!(
+            "cannot merge analyzer {}, it is not a fork of this one",
+            name
+        ))
+    })
+}
+
+/// A titled table reported by an analyzer.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Section {
+    /// The analyzer's name.
+    pub name: String,
+    pub title: String,
+    pub columns: Vec<String>,
+    /// One value per column in every row, strings being shown as is.
+    pub rows: Vec<Vec<Value>>,
+}
+
+/// Messages per value of a field, most frequent first.
+#[derive(Debug, Clone)]
+pub struct FieldCounts {
+    name: String,
+    field: String,
+    // Number of values reported.
+    top: usize,
+    // Messages per value, and messages with the field at all.
+    counts: HashMap<String, u64>,
+    total: u64,
+}
+
+impl FieldCounts {
+    /// Count the values of `field`, reporting the `top` most frequent.
+    pub fn new(name: &str, field: &str, top: usize) -> Self {
+        Self {
+            name: name.to_string(),
+            field: field.to_string(),
+            top,
+            counts: HashMap::new(),
+            total: 0,
+        }
+    }
+}
+
+impl Analyzer for FieldCounts {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn observe(&mut self, _message: &Message, entry: &Entry) {
+        if let Some(value) = entry.get(&self.field) {
+            *self.counts.entry(value.clone()).or_insert(0) += 1;
+            self.total += 1;
+        }
+    }
+
+    fn fork(&self) -> Box<dyn Analyzer> {
+        Box::new(Self::new(&self.name, &self.field, self.top))
+    }
+
+    fn merge(&mut self, other: Box<dyn Analyzer>) -> crate::Result<()> {
+        let other = downcast::<Self>(other)?;
+        for (value, count) in other.counts {
+            *self.counts.entry(value).or_insert(0) += count;
+        }
+        self.total += other.total;
+        Ok(())
+    }
+
+    fn finish(&self) -> Section {
+        let rows = footprint::ranked(&self.counts)
+            .into_iter()
+            .take(self.top)
+            .enumerate()
+            .map(|(i, (value, count))| {
+                let percent = count as f64 / self.total as f64 * 100.0;
+                vec![
+                    (i + 1).into(),
+                    value.as_str().into(),
+                    count.into(),
+                    ((percent * 100.0).round() / 100.0).into(),
+                ]
+            })
+            .collect();
+
+        Section {
+            name: self.name.clone(),
+            title: format!("Messages per {}:", self.field),
+            columns: ["Rank", "Value", "Messages", "Percent"]
+                .map(String::from)
+                .to_vec(),
+            rows,
+        }
+    }
+}
+
+/// The analyzers that can be asked for by name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Builtin {
+    /// Messages per systemd unit.
+    Units,
+    /// Messages per host.
+    Hosts,
+    /// Messages per value of any field.
+    Field(String),
+}
+
+impl FromStr for Builtin {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "units" => Ok(Builtin::Units),
+            None if s == "hosts" => Ok(Builtin::Hosts),
+            Some(("field", field)) if !field.is_empty() => Ok(Builtin::Field(field.to_string())),
+            _ => Err(format!(
+                "unknown analyzer '{}', expected 'units', 'hosts' or 'field:NAME'",
+                s
+            )),
+        }
+    }
+}
+
+impl Builtin {
+    /// Create the analyzer, reporting `top` rows.
+    pub fn create(&self, top: usize) -> Box<dyn Analyzer> {
+        match self {
+            Builtin::Units => Box::new(FieldCounts::new("units", "_SYSTEMD_UNIT", top)),
+            Builtin::Hosts => Box::new(FieldCounts::new("hosts", "_HOSTNAME", top)),
+            Builtin::Field(field) => {
+                Box::new(FieldCounts::new(&format!("field:{}", field), field, top))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn observe(analyzer: &mut dyn Analyzer, units: &[&str]) {
+        let message = Message {
+            msg: "hi".to_string(),
+            process: "app".to_string(),
+            priority: "6".to_string(),
+        };
+        for unit in units {
+            let entry: Entry = [("_SYSTEMD_UNIT".to_string(), unit.to_string())].into();
+            analyzer.observe(&message, &entry);
+        }
+    }
+
+    #[test]
+    fn forks_merge_into_one_section() {
+        let mut units = "units".parse::<Builtin>().unwrap().create(2);
+        observe(&mut *units, &["a.service", "b.service"]);
+
+        let mut fork = units.fork();
+        observe(&mut *fork, &["b.service", "c.service"]);
+        units.merge(fork).unwrap();
+
+        let section = units.finish();
+        assert_eq!(section.name, "units");
+        assert_eq!(section.title, "Messages per _SYSTEMD_UNIT:");
+        assert_eq!(
+            section.rows,
+            vec![
+                vec![json!(1), json!("b.service"), json!(2), json!(50.0)],
+                vec![json!(2), json!("a.service"), json!(1), json!(25.0)],
+            ]
+        );
+
+        assert_eq!(
+            "field:_UID".parse::<Builtin>(),
+            Ok(Builtin::Field("_UID".to_string()))
+        );
+        assert!("field:".parse::<Builtin>().is_err());
+        assert!("bogus".parse::<Builtin>().is_err());
+    }
+}
@@ -0,0 +1,314 @@
+//! Boot selection and per boot statistics, keyed on the `_BOOT_ID` field.
+
+use crate::{
+    analyzer::{self, Analyzer, Section},
+    report::{self, OutputFormat, Report},
+    source::{self, Entry, EntrySource},
+    time, Message,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    io::{self, Write},
+    str::FromStr,
+};
+use tabled::Tabled;
+
+/// The field identifying the boot an entry was logged in.
+pub const BOOT_ID: &str = "_BOOT_ID";
+
+/// A boot selected on the command line, either as an offset like journalctl
+/// (0 is the last boot, -1 the one before, 1 the first) or as a boot ID.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BootSpec {
+    Offset(i64),
+    Id(String),
+}
+
+impl FromStr for BootSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(offset) = s.parse() {
+            return Ok(BootSpec::Offset(offset));
+        }
+
+        // Accept IDs in UUID form as well as the journal's plain hex.
+        let id: String = s.chars().filter(|c| *c != '-').collect();
+        if id.len() == 32 && id.chars().all(|c| c.is_ascii_hexdigit()) {
+            Ok(BootSpec::Id(id.to_ascii_lowercase()))
+        } else {
+            Err(format!(
+                "invalid boot '{}', expected an offset or a boot ID",
+                s
+            ))
+        }
+    }
+}
+
+/// Statistics for a single boot.
+#[derive(Debug, Default, Clone)]
+pub struct Boot {
+    /// The boot ID.
+    pub id: String,
+    /// Timestamps of the first and last entries of the boot.
+    pub first: Option<u64>,
+    pub last: Option<u64>,
+    /// Number of entries in the boot.
+    pub count: u64,
+    /// Number of messages per process in the boot.
+    pub per_process: HashMap<String, u32>,
+}
+
+impl Boot {
+    /// Account for an entry belonging to this boot.
+    pub fn observe(&mut self, entry: &Entry) {
+        if let Some(ts) = source::timestamp(entry) {
+            self.first = Some(self.first.map_or(ts, |first| first.min(ts)));
+            self.last = Some(self.last.map_or(ts, |last| last.max(ts)));
+        }
+        self.count += 1;
+    }
+}
+
+/// Boots keyed by boot ID.
+#[derive(Debug, Default)]
+pub struct Boots {
+    boots: HashMap<String, Boot>,
+}
+
+impl Boots {
+    /// The boot record for `entry`, created on first sight, or `None` if the
+    /// entry has no boot ID. Such entries are left out like journalctl does,
+    /// so they do not shift the boot offsets.
+    pub fn get_mut(&mut self, entry: &Entry) -> Option<&mut Boot> {
+        let id = entry.get(BOOT_ID)?;
+        Some(self.boots.entry(id.clone()).or_insert_with(|| Boot {
+            id: id.clone(),
+            ..Default::default()
+        }))
+    }
+
+    /// All boots, oldest first.
+    pub fn sorted(&self) -> Vec<&Boot> {
+        let mut boots: Vec<&Boot> = self.boots.values().collect();
+        boots.sort_by(|a, b| a.first.cmp(&b.first).then_with(|| a.id.cmp(&b.id)));
+        boots
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.boots.is_empty()
+    }
+}
+
+#[derive(Tabled)]
+#[allow(non_snake_case)]
+struct PerBootProcessTableEntry<'a> {
+    Rank: usize,
+    Process: &'a str,
+    Percent: String,
+    New: &'a str,
+}
+
+impl Analyzer for Boots {
+    fn name(&self) -> &str {
+        "per_boot"
+    }
+
+    fn observe(&mut self, message: &Message, entry: &Entry) {
+        if let Some(boot) = self.get_mut(entry) {
+            boot.observe(entry);
+            *boot.per_process.entry(message.process.clone()).or_insert(0) += 1;
+        }
+    }
+
+    fn fork(&self) -> Box<dyn Analyzer> {
+        Box::<Self>::default()
+    }
+
+    /// Add the boots and counts of `other`.
+    fn merge(&mut self, other: Box<dyn Analyzer>) -> crate::Result<()> {
+        let other = analyzer::downcast::<Self>(other)?;
+        for (id, theirs) in other.boots {
+            let boot = self.boots.entry(id).or_insert_with(|| Boot {
+                id: theirs.id.clone(),
+                ..Default::default()
+            });
+            boot.first = boot.first.into_iter().chain(theirs.first).min();
+            boot.last = boot.last.into_iter().chain(theirs.last).max();
+            boot.count += theirs.count;
+            for (process, count) in theirs.per_process {
+                *boot.per_process.entry(process).or_insert(0) += count;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&self) -> Section {
+        Section {
+            name: self.name().to_string(),
+            title: "Per boot messages".to_string(),
+            columns: ["Boot", "First", "Last", "Messages", "Processes"]
+                .map(String::from)
+                .to_vec(),
+            rows: self
+                .sorted()
+                .into_iter()
+                .map(|boot| {
+                    vec![
+                        boot.id.as_str().into(),
+                        boot.first.map(time::format_time).into(),
+                        boot.last.map(time::format_time).into(),
+                        boot.count.into(),
+                        boot.per_process.len().into(),
+                    ]
+                })
+                .collect(),
+        }
+    }
+
+    fn report<'a>(&'a self, document: &mut Report<'a>) {
+        document.per_boot = Some(
+            self.sorted()
+                .into_iter()
+                .map(|boot| report::BootShare {
+                    boot_id: &boot.id,
+                    first_entry: boot.first,
+                    last_entry: boot.last,
+                    messages: boot.count,
+                    per_process: report::process_shares(&boot.per_process, boot.count),
+                })
+                .collect(),
+        );
+    }
+
+    fn write_tables(&self, format: OutputFormat, out: &mut dyn Write) -> io::Result<()> {
+        if self.is_empty() {
+            return Ok(());
+        }
+
+        if format == OutputFormat::Table {
+            writeln!(out, "Per boot process message allocations")?;
+        }
+
+        // Processes logging in an earlier boot, to flag those new to a boot.
+        let mut earlier: HashSet<&str> = HashSet::new();
+
+        for boot in self.sorted() {
+            let title = format!(
+                "Boot {}, {} to {}",
+                boot.id,
+                boot.first.map_or("-".to_string(), time::format_time),
+                boot.last.map_or("-".to_string(), time::format_time)
+            );
+
+            let mut pp_vec: Vec<(&String, &u32)> = boot.per_process.iter().collect();
+            pp_vec.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+
+            let mut table = Vec::new();
+
+            for (i, (process, nmsgs)) in pp_vec.iter().enumerate() {
+                table.push(PerBootProcessTableEntry {
+                    Rank: i + 1,
+                    Process: process,
+                    Percent: format!("{:.02}", ((**nmsgs as f32 / boot.count as f32) * 100.0)),
+                    New: if earlier.contains(process.as_str()) {
+                        ""
+                    } else {
+                        "yes"
+                    },
+                });
+            }
+
+            writeln!(out, "{}", report::table(format, &title, table))?;
+
+            earlier.extend(boot.per_process.keys().map(|p| p.as_str()));
+        }
+        Ok(())
+    }
+}
+
+/// Read every entry of `source` and list the boots found, oldest first.
+pub fn list_boots(source: &mut dyn EntrySource) -> io::Result<Vec<Boot>> {
+    let mut boots = Boots::default();
+    while let Some(entry) = source.next_entry()? {
+        if let Some(boot) = boots.get_mut(&entry) {
+            boot.observe(&entry);
+        }
+    }
+
+    Ok(boots.sorted().into_iter().cloned().collect())
+}
+
+/// Turn a journalctl style boot offset into a boot ID, `boots` oldest first.
+pub fn resolve_offset(offset: i64, boots: &[Boot]) -> Result<String, String> {
+    let n = boots.len() as i64;
+    let idx = if offset > 0 {
+        offset - 1
+    } else {
+        n - 1 + offset
+    };
+
+    if (0..n).contains(&idx) {
+        Ok(boots[idx as usize].id.clone())
+    } else {
+        Err(format!(
+            "no boot at offset {}, the journal has {} boots",
+            offset, n
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn boot(id: &str) -> Boot {
+        Boot {
+            id: id.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn parses_offsets_and_ids() {
+        assert_eq!("-1".parse(), Ok(BootSpec::Offset(-1)));
+        assert_eq!("0".parse(), Ok(BootSpec::Offset(0)));
+        assert_eq!(
+            "3A0B8A4E-0F1D-4C6B-9E2A-0123456789AB".parse(),
+            Ok(BootSpec::Id("3a0b8a4e0f1d4c6b9e2a0123456789ab".to_string()))
+        );
+        assert!("last".parse::<BootSpec>().is_err());
+    }
+
+    #[test]
+    fn resolves_offsets_like_journalctl() {
+        let boots = [boot("a"), boot("b"), boot("c")];
+
+        assert_eq!(resolve_offset(0, &boots), Ok("c".to_string()));
+        assert_eq!(resolve_offset(-2, &boots), Ok("a".to_string()));
+        assert_eq!(resolve_offset(1, &boots), Ok("a".to_string()));
+        assert!(resolve_offset(-3, &boots).is_err());
+        assert!(resolve_offset(4, &boots).is_err());
+    }
+
+    #[test]
+    fn leaves_entries_without_a_boot_out_of_the_offsets() {
+        let entry = |boot: Option<&str>, ts: u64| -> Entry {
+            let mut entry: Entry = [(source::REALTIME_TIMESTAMP.to_string(), ts.to_string())]
+                .into_iter()
+                .collect();
+            if let Some(boot) = boot {
+                entry.insert(BOOT_ID.to_string(), boot.to_string());
+            }
+            entry
+        };
+        let mut source =
+            vec![entry(Some("a"), 10), entry(None, 5), entry(Some("b"), 20)].into_iter();
+
+        let boots = list_boots(&mut source).unwrap();
+        let ids: Vec<&str> = boots.iter().map(|boot| boot.id.as_str()).collect();
+        assert_eq!(ids, ["a", "b"]);
+        assert_eq!(resolve_offset(0, &boots), Ok("b".to_string()));
+        assert_eq!(resolve_offset(-1, &boots), Ok("a".to_string()));
+    }
+}
@@ -0,0 +1,566 @@
+//! Detection of bursts, periods where a process or message template logs
+//! faster than a fixed rate or much faster than its own recent baseline.
+//!
+//! Entries are counted per key in fixed windows of their timestamp. A window
+//! is part of a burst when its count reaches `--burst-rate`, or when it is at
+//! least `--burst-factor` times the key's baseline, an exponentially weighted
+//! moving average of its earlier windows that were not bursts. Consecutive
+//! burst windows are merged into a single burst.
+
+use crate::{
+    analyzer::{Analyzer, Section},
+    histogram::Interval,
+    report::{self, OutputFormat, Report},
+    source::{self, Entry},
+    template::Masker,
+    time, Error, Message,
+};
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{self, Write},
+    str::FromStr,
+};
+use tabled::Tabled;
+
+/// Window used when only `--burst-factor` is given.
+const DEFAULT_WINDOW: Interval = Interval(60 * 1_000_000);
+
+/// Weight of the latest window in the baseline.
+const BASELINE_ALPHA: f64 = 0.1;
+
+/// Windows a key must have been seen for before its baseline is trusted.
+const BASELINE_WARMUP: u64 = 5;
+
+/// Fewest messages in a window for it to count as a burst on its baseline
+/// alone, so that a quiet process logging a handful of lines is not flagged.
+const BASELINE_MIN_MESSAGES: u64 = 10;
+
+/// What bursts are tracked for.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum BurstKey {
+    #[default]
+    Process,
+    /// The process and the masked message, as with `--cluster mask`.
+    Template,
+}
+
+impl FromStr for BurstKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "process" => Ok(BurstKey::Process),
+            "template" => Ok(BurstKey::Template),
+            _ => Err(format!(
+                "unknown burst key '{}', expected 'process' or 'template'",
+                s
+            )),
+        }
+    }
+}
+
+/// A number of messages per window, such as "1000/min".
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rate {
+    pub count: u64,
+    pub window: Interval,
+}
+
+impl FromStr for Rate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || {
+            format!(
+                "invalid rate '{}', expected a count per interval such as 1000/min",
+                s
+            )
+        };
+
+        let (count, window) = s.split_once('/').ok_or_else(err)?;
+        let count = count.trim().parse().map_err(|_| err())?;
+        let window = window.trim();
+        // Allow "1000/min" as well as "1000/1min".
+        let window = if window.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            window.parse().or_else(|_| format!("1{}", window).parse())
+        } else {
+            window.parse()
+        }?;
+
+        Ok(Rate { count, window })
+    }
+}
+
+impl fmt::Display for Rate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.count, self.window)
+    }
+}
+
+/// When a window counts as a burst.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct BurstConfig {
+    /// A fixed rate to flag.
+    pub rate: Option<Rate>,
+    /// How many times its baseline a key must log to be flagged.
+    pub factor: Option<f64>,
+    pub key: BurstKey,
+}
+
+impl BurstConfig {
+    /// Whether any detection was asked for.
+    pub fn enabled(&self) -> bool {
+        self.rate.is_some() || self.factor.is_some()
+    }
+
+    /// Width of the windows counted.
+    pub fn window(&self) -> Interval {
+        self.rate.map_or(DEFAULT_WINDOW, |rate| rate.window)
+    }
+}
+
+/// A period of unusually high logging.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Burst {
+    pub process: String,
+    /// The masked message, when tracking templates.
+    pub template: Option<String>,
+    /// Start of the first window and end of the last window of the burst.
+    pub start: u64,
+    pub end: u64,
+    /// Most messages in one window.
+    pub peak: u64,
+    /// Messages over the whole burst.
+    pub messages: u64,
+    /// Messages per window usually logged, once known.
+    pub baseline: Option<f64>,
+    /// The most frequent message of the burst.
+    pub message: String,
+}
+
+#[derive(Debug, Clone)]
+struct Window {
+    start: u64,
+    count: u64,
+    messages: HashMap<String, u64>,
+}
+
+/// The state kept for each key.
+#[derive(Debug, Default, Clone)]
+struct Track {
+    window: Option<Window>,
+    baseline: f64,
+    windows_seen: u64,
+    // The burst in progress and the messages counted in it.
+    burst: Option<(Burst, HashMap<String, u64>)>,
+}
+
+/// Finds bursts in entries fed in timestamp order.
+#[derive(Clone)]
+pub struct BurstDetector {
+    config: BurstConfig,
+    window: u64,
+    masker: Option<Masker>,
+    tracks: HashMap<(String, Option<String>), Track>,
+    bursts: Vec<Burst>,
+    // The bursts found when last completed, including those still going on.
+    reported: Vec<Burst>,
+}
+
+impl BurstDetector {
+    pub fn new(config: BurstConfig) -> Self {
+        Self {
+            config,
+            window: config.window().0,
+            masker: (config.key == BurstKey::Template).then(Masker::default),
+            tracks: HashMap::new(),
+            bursts: Vec::new(),
+            reported: Vec::new(),
+        }
+    }
+
+    pub fn config(&self) -> &BurstConfig {
+        &self.config
+    }
+
+    /// Count a message logged by `process` at `ts`.
+    pub fn add(&mut self, ts: u64, process: &str, msg: &str) {
+        let start = ts - ts % self.window;
+        let key = (
+            process.to_string(),
+            self.masker.as_ref().map(|masker| masker.mask(msg)),
+        );
+
+        let track = self.tracks.entry(key.clone()).or_default();
+        if track.window.as_ref().is_some_and(|w| start > w.start) {
+            close(
+                &self.config,
+                self.window,
+                &key,
+                track,
+                Some(start),
+                &mut self.bursts,
+            );
+        }
+
+        // Entries slightly out of order are counted in the current window.
+        let window = track.window.get_or_insert_with(|| Window {
+            start,
+            count: 0,
+            messages: HashMap::new(),
+        });
+        window.count += 1;
+        *window.messages.entry(msg.to_string()).or_insert(0) += 1;
+    }
+
+    /// Close the open windows and return the bursts found, oldest first.
+    pub fn close_windows(&mut self) -> &[Burst] {
+        for (key, track) in self.tracks.iter_mut() {
+            if track.window.is_some() {
+                close(
+                    &self.config,
+                    self.window,
+                    key,
+                    track,
+                    None,
+                    &mut self.bursts,
+                );
+            }
+            end_burst(track, &mut self.bursts);
+        }
+
+        sort(&mut self.bursts);
+        &self.bursts
+    }
+
+    /// The bursts found by `close_windows`.
+    pub fn bursts(&self) -> &[Burst] {
+        &self.bursts
+    }
+}
+
+#[derive(Tabled)]
+#[allow(non_snake_case)]
+struct BurstTableEntry<'a> {
+    Start: String,
+    End: String,
+    Process: &'a str,
+    Peak: String,
+    Messages: u64,
+    Baseline: String,
+    Message: &'a str,
+}
+
+impl Analyzer for BurstDetector {
+    fn name(&self) -> &str {
+        "bursts"
+    }
+
+    /// Count an entry in the window of its timestamp, if it has one.
+    fn observe(&mut self, message: &Message, entry: &Entry) {
+        if let Some(ts) = source::timestamp(entry) {
+            self.add(ts, &message.process, &message.msg);
+        }
+    }
+
+    fn fork(&self) -> Box<dyn Analyzer> {
+        Box::new(Self::new(self.config))
+    }
+
+    fn merge(&mut self, _other: Box<dyn Analyzer>) -> crate::Result<()> {
+        Err(Error::Invalid(
+            "bursts depend on the order of entries and cannot be merged".to_string(),
+        ))
+    }
+
+    fn mergeable(&self) -> bool {
+        false
+    }
+
+    /// Find the bursts so far, reporting those still going on without ending
+    /// them for good.
+    fn complete(&mut self) {
+        self.reported.clone_from(&self.bursts);
+        for (key, track) in &self.tracks {
+            if track.window.is_none() && track.burst.is_none() {
+                continue;
+            }
+
+            // Close a copy of the open window and burst, the track going on.
+            let mut pending = Track {
+                window: track.window.clone(),
+                burst: track.burst.clone(),
+                ..*track
+            };
+            if pending.window.is_some() {
+                close(
+                    &self.config,
+                    self.window,
+                    key,
+                    &mut pending,
+                    None,
+                    &mut self.reported,
+                );
+            }
+            end_burst(&mut pending, &mut self.reported);
+        }
+        sort(&mut self.reported);
+    }
+
+    fn finish(&self) -> Section {
+        Section {
+            name: self.name().to_string(),
+            title: "Bursts:".to_string(),
+            columns: [
+                "Start", "End", "Process", "Peak", "Messages", "Baseline", "Message",
+            ]
+            .map(String::from)
+            .to_vec(),
+            rows: self
+                .reported
+                .iter()
+                .map(|burst| {
+                    vec![
+                        time::format_time(burst.start).into(),
+                        time::format_time(burst.end).into(),
+                        burst.process.as_str().into(),
+                        burst.peak.into(),
+                        burst.messages.into(),
+                        burst.baseline.map(|b| (b * 100.0).round() / 100.0).into(),
+                        burst.message.as_str().into(),
+                    ]
+                })
+                .collect(),
+        }
+    }
+
+    fn report<'a>(&'a self, document: &mut Report<'a>) {
+        document.bursts = Some(
+            self.reported
+                .iter()
+                .map(|burst| report::BurstReport {
+                    process: &burst.process,
+                    template: burst.template.as_deref(),
+                    start: burst.start,
+                    end: burst.end,
+                    window_usec: self.window,
+                    peak: burst.peak,
+                    messages: burst.messages,
+                    baseline: burst.baseline,
+                    message: &burst.message,
+                })
+                .collect(),
+        );
+    }
+
+    fn write_tables(&self, format: OutputFormat, out: &mut dyn Write) -> io::Result<()> {
+        let window = self.config.window();
+        let table = self
+            .reported
+            .iter()
+            .map(|burst| BurstTableEntry {
+                Start: time::format_time(burst.start),
+                End: time::format_time(burst.end),
+                Process: &burst.process,
+                Peak: format!("{}/{}", burst.peak, window),
+                Messages: burst.messages,
+                Baseline: burst
+                    .baseline
+                    .map_or("-".to_string(), |b| format!("{:.1}/{}", b, window)),
+                Message: &burst.message,
+            })
+            .collect();
+
+        writeln!(out, "{}", report::table(format, "Bursts:", table))
+    }
+}
+
+/// Evaluate the open window of a track, `next` being the start of the window
+/// that follows it if there is one.
+fn close(
+    config: &BurstConfig,
+    width: u64,
+    key: &(String, Option<String>),
+    track: &mut Track,
+    next: Option<u64>,
+    bursts: &mut Vec<Burst>,
+) {
+    let window = track.window.take().unwrap();
+    let baseline = (track.windows_seen >= BASELINE_WARMUP).then_some(track.baseline);
+
+    let over_rate = config.rate.is_some_and(|rate| window.count >= rate.count);
+    let over_baseline = match (config.factor, baseline) {
+        (Some(factor), Some(baseline)) => {
+            window.count >= BASELINE_MIN_MESSAGES && window.count as f64 >= factor * baseline
+        }
+        _ => false,
+    };
+
+    if over_rate || over_baseline {
+        match &mut track.burst {
+            Some((burst, messages)) => {
+                burst.end = window.start + width;
+                burst.peak = burst.peak.max(window.count);
+                burst.messages += window.count;
+                for (msg, count) in window.messages {
+                    *messages.entry(msg).or_insert(0) += count;
+                }
+            }
+            None => {
+                track.burst = Some((
+                    Burst {
+                        process: key.0.clone(),
+                        template: key.1.clone(),
+                        start: window.start,
+                        end: window.start + width,
+                        peak: window.count,
+                        messages: window.count,
+                        baseline,
+                        message: String::new(),
+                    },
+                    window.messages,
+                ));
+            }
+        }
+    } else {
+        end_burst(track, bursts);
+
+        // Bursts are left out of the baseline, so that a long storm is not
+        // taken as the new normal.
+        track.baseline = if track.windows_seen == 0 {
+            window.count as f64
+        } else {
+            BASELINE_ALPHA * window.count as f64 + (1.0 - BASELINE_ALPHA) * track.baseline
+        };
+    }
+    track.windows_seen += 1;
+
+    // Windows without any messages end a burst and lower the baseline.
+    let empty = next.map_or(0, |next| (next - window.start) / width - 1);
+    if empty > 0 {
+        end_burst(track, bursts);
+        track.baseline *= (1.0 - BASELINE_ALPHA).powi(empty.min(i32::MAX as u64) as i32);
+        track.windows_seen += empty;
+    }
+}
+
+/// Order bursts by start, then by key.
+fn sort(bursts: &mut [Burst]) {
+    bursts.sort_by(|a, b| {
+        a.start
+            .cmp(&b.start)
+            .then_with(|| a.process.cmp(&b.process))
+            .then_with(|| a.template.cmp(&b.template))
+    });
+}
+
+fn end_burst(track: &mut Track, bursts: &mut Vec<Burst>) {
+    if let Some((mut burst, messages)) = track.burst.take() {
+        burst.message = messages
+            .into_iter()
+            .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(&a.0)))
+            .map(|(msg, _)| msg)
+            .unwrap_or_default();
+        bursts.push(burst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: u64 = 60 * 1_000_000;
+
+    fn detector(rate: Option<&str>, factor: Option<f64>, key: BurstKey) -> BurstDetector {
+        BurstDetector::new(BurstConfig {
+            rate: rate.map(|r| r.parse().unwrap()),
+            factor,
+            key,
+        })
+    }
+
+    #[test]
+    fn parses_rates() {
+        let rate: Rate = "1000/min".parse().unwrap();
+        assert_eq!((rate.count, rate.window), (1000, Interval(MINUTE)));
+        assert_eq!(
+            "50/5min".parse::<Rate>().unwrap().window,
+            Interval(5 * MINUTE)
+        );
+        assert_eq!("20/hour".parse::<Rate>().unwrap().to_string(), "20/1h");
+        assert!("1000".parse::<Rate>().is_err());
+        assert!("many/min".parse::<Rate>().is_err());
+    }
+
+    #[test]
+    fn flags_windows_over_the_rate() {
+        let mut d = detector(Some("3/min"), None, BurstKey::Process);
+        for minute in [0, 1, 1, 1, 2, 2, 2, 2, 3, 5, 5, 5] {
+            d.add(
+                minute * MINUTE + 1,
+                "app",
+                if minute == 2 { "b" } else { "a" },
+            );
+        }
+        d.add(2 * MINUTE, "cron", "tick");
+
+        let bursts = d.close_windows();
+        assert_eq!(bursts.len(), 2);
+        assert_eq!(
+            (
+                bursts[0].start,
+                bursts[0].end,
+                bursts[0].peak,
+                bursts[0].messages
+            ),
+            (MINUTE, 3 * MINUTE, 4, 7)
+        );
+        assert_eq!(bursts[0].message, "b");
+        assert_eq!((bursts[1].start, bursts[1].messages), (5 * MINUTE, 3));
+    }
+
+    #[test]
+    fn completing_reports_bursts_going_on_without_ending_them() {
+        let mut d = detector(Some("3/min"), None, BurstKey::Process);
+        for i in 0..3 {
+            d.add(MINUTE + i, "app", "a");
+        }
+        d.complete();
+        assert_eq!(d.reported.len(), 1);
+        assert_eq!(d.finish().rows[0].len(), d.finish().columns.len());
+
+        for i in 0..3 {
+            d.add(2 * MINUTE + i, "app", "a");
+        }
+        d.complete();
+        assert_eq!(d.reported.len(), 1);
+        assert_eq!((d.reported[0].end, d.reported[0].messages), (3 * MINUTE, 6));
+    }
+
+    #[test]
+    fn flags_deviations_from_the_baseline() {
+        let mut d = detector(None, Some(5.0), BurstKey::Template);
+        for minute in 0..10 {
+            for i in 0..2 {
+                d.add(minute * MINUTE + i, "app", &format!("job {} done", i));
+            }
+        }
+        // A new template has no baseline yet and is not flagged.
+        for i in 0..30 {
+            d.add(10 * MINUTE + i, "app", &format!("job {} done", i % 3));
+            d.add(10 * MINUTE + i, "app", &format!("retry {}", i));
+        }
+        d.add(11 * MINUTE, "app", "job 0 done");
+
+        let bursts = d.close_windows();
+        assert_eq!(bursts.len(), 1);
+        assert_eq!(bursts[0].template.as_deref(), Some("job <NUM> done"));
+        assert_eq!(bursts[0].baseline, Some(2.0));
+        assert_eq!((bursts[0].start, bursts[0].end), (10 * MINUTE, 11 * MINUTE));
+        assert_eq!(
+            (bursts[0].messages, bursts[0].message.as_str()),
+            (30, "job 0 done")
+        );
+    }
+}
@@ -0,0 +1,382 @@
+//! Number of distinct values fields take, overall and per process, to find
+//! processes logging high cardinality data such as unique IDs in messages.
+//!
+//! Values are counted exactly until a field has more than [`EXACT_LIMIT`]
+//! of them, then estimated with HyperLogLog (Flajolet, Fusy, Gandouet and
+//! Meunier, 2007) in a fixed 4 KiB per field and process. Estimates have a
+//! standard error of about 1.6%.
+
+use crate::{
+    analyzer::{self, Analyzer, Section},
+    report::{self, OutputFormat, Report},
+    source::Entry,
+    Message,
+};
+use std::{
+    borrow::Cow,
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    fmt,
+    hash::{Hash, Hasher},
+    io::{self, Write},
+};
+use tabled::Tabled;
+
+/// Fields counted unless others are asked for.
+pub const DEFAULT_FIELDS: &[&str] = &["MESSAGE", "_PID", "_COMM", "_SYSTEMD_UNIT", "_HOSTNAME"];
+
+/// Distinct values counted exactly before estimating.
+pub const EXACT_LIMIT: usize = 1024;
+
+// Bits of the hash selecting a register, and the number of registers.
+const PRECISION: u32 = 12;
+const REGISTERS: usize = 1 << PRECISION;
+
+/// Distinct values of one field.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Distinct {
+    /// Hashes of every value seen, up to [`EXACT_LIMIT`].
+    Exact(HashSet<u64>),
+    /// HyperLogLog registers, the most leading zeros plus one seen in the
+    /// hashes falling into each.
+    Sketch(Box<[u8]>),
+}
+
+impl Default for Distinct {
+    fn default() -> Self {
+        Distinct::Exact(HashSet::new())
+    }
+}
+
+impl Distinct {
+    /// Count `value`.
+    pub fn insert(&mut self, value: &str) {
+        // DefaultHasher::new() uses fixed keys, so hashes are the same in
+        // every thread and run, as merging needs.
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        self.insert_hash(hasher.finish());
+    }
+
+    fn insert_hash(&mut self, hash: u64) {
+        match self {
+            Distinct::Exact(hashes) => {
+                hashes.insert(hash);
+                if hashes.len() > EXACT_LIMIT {
+                    let mut registers = vec![0; REGISTERS].into_boxed_slice();
+                    for hash in hashes.iter() {
+                        Self::update(&mut registers, *hash);
+                    }
+                    *self = Distinct::Sketch(registers);
+                }
+            }
+            Distinct::Sketch(registers) => Self::update(registers, hash),
+        }
+    }
+
+    fn update(registers: &mut [u8], hash: u64) {
+        let register = (hash >> (64 - PRECISION)) as usize;
+        // The low bit stops the count once the register bits are shifted out.
+        let rank = ((hash << PRECISION) | 1 << (PRECISION - 1)).leading_zeros() as u8 + 1;
+        registers[register] = registers[register].max(rank);
+    }
+
+    /// Whether the count is exact rather than estimated.
+    pub fn is_exact(&self) -> bool {
+        matches!(self, Distinct::Exact(_))
+    }
+
+    /// Number of distinct values seen, or its estimate.
+    pub fn count(&self) -> u64 {
+        let registers = match self {
+            Distinct::Exact(hashes) => return hashes.len() as u64,
+            Distinct::Sketch(registers) => registers,
+        };
+
+        let m = REGISTERS as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum: f64 = registers.iter().map(|r| 2f64.powi(-(*r as i32))).sum();
+        let estimate = alpha * m * m / sum;
+
+        // Linear counting is more accurate while registers are still empty.
+        let empty = registers.iter().filter(|r| **r == 0).count();
+        if estimate <= 2.5 * m && empty > 0 {
+            (m * (m / empty as f64).ln()).round() as u64
+        } else {
+            estimate.round() as u64
+        }
+    }
+
+    /// Add the values counted by `other`.
+    pub fn merge(&mut self, other: Distinct) {
+        match other {
+            Distinct::Exact(hashes) => {
+                for hash in hashes {
+                    self.insert_hash(hash);
+                }
+            }
+            Distinct::Sketch(theirs) => {
+                if let Distinct::Exact(hashes) = self {
+                    let mut registers = theirs;
+                    for hash in hashes.iter() {
+                        Self::update(&mut registers, *hash);
+                    }
+                    *self = Distinct::Sketch(registers);
+                } else if let Distinct::Sketch(mine) = self {
+                    for (mine, theirs) in mine.iter_mut().zip(theirs.iter()) {
+                        *mine = (*mine).max(*theirs);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The count, marked with `~` when estimated.
+impl fmt::Display for Distinct {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.is_exact() {
+            true => write!(f, "{}", self.count()),
+            false => write!(f, "~{}", self.count()),
+        }
+    }
+}
+
+/// Distinct values of a set of fields, overall and per process.
+#[derive(Debug, Clone, Default)]
+pub struct Cardinality {
+    fields: Vec<String>,
+    overall: HashMap<String, Distinct>,
+    per_process: HashMap<String, HashMap<String, Distinct>>,
+}
+
+impl Cardinality {
+    /// Count the values of `fields`.
+    pub fn new(fields: Vec<String>) -> Self {
+        Self {
+            fields,
+            ..Self::default()
+        }
+    }
+
+    /// The fields counted, in the order asked for.
+    pub fn fields(&self) -> &[String] {
+        &self.fields
+    }
+
+    /// Distinct values of every field over all processes.
+    pub fn overall(&self) -> &HashMap<String, Distinct> {
+        &self.overall
+    }
+
+    /// Distinct values of every field per process, the processes with the
+    /// most values of any one field first, ties broken by name.
+    pub fn per_process(&self) -> Vec<(&String, &HashMap<String, Distinct>)> {
+        let most = |fields: &HashMap<String, Distinct>| {
+            fields.values().map(Distinct::count).max().unwrap_or(0)
+        };
+
+        let mut ranked: Vec<_> = self
+            .per_process
+            .iter()
+            .map(|(process, fields)| (most(fields), process, fields))
+            .collect();
+        ranked.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(b.1)));
+        ranked
+            .into_iter()
+            .map(|(_, process, fields)| (process, fields))
+            .collect()
+    }
+
+    /// The fields counted that some entry had, in the order asked for.
+    fn fields_seen(&self) -> Vec<&String> {
+        self.fields
+            .iter()
+            .filter(|f| self.overall.contains_key(*f))
+            .collect()
+    }
+}
+
+#[derive(Tabled)]
+#[allow(non_snake_case)]
+struct CardinalityTableEntry<'a> {
+    Field: &'a str,
+    Values: String,
+}
+
+impl Analyzer for Cardinality {
+    fn name(&self) -> &str {
+        "cardinality"
+    }
+
+    fn observe(&mut self, message: &Message, entry: &Entry) {
+        let per_process = self.per_process.entry(message.process.clone()).or_default();
+
+        for field in &self.fields {
+            if let Some(value) = entry.get(field) {
+                self.overall.entry(field.clone()).or_default().insert(value);
+                per_process.entry(field.clone()).or_default().insert(value);
+            }
+        }
+    }
+
+    fn fork(&self) -> Box<dyn Analyzer> {
+        Box::new(Self::new(self.fields.clone()))
+    }
+
+    fn merge(&mut self, other: Box<dyn Analyzer>) -> crate::Result<()> {
+        let other = analyzer::downcast::<Self>(other)?;
+        for (field, distinct) in other.overall {
+            self.overall.entry(field).or_default().merge(distinct);
+        }
+        for (process, fields) in other.per_process {
+            let mine = self.per_process.entry(process).or_default();
+            for (field, distinct) in fields {
+                mine.entry(field).or_default().merge(distinct);
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&self) -> Section {
+        Section {
+            name: self.name().to_string(),
+            title: "Distinct values per field (~ estimated)".to_string(),
+            columns: vec!["Field".to_string(), "Values".to_string()],
+            rows: self
+                .fields_seen()
+                .into_iter()
+                .map(|field| {
+                    vec![
+                        field.as_str().into(),
+                        self.overall[field].to_string().into(),
+                    ]
+                })
+                .collect(),
+        }
+    }
+
+    fn report<'a>(&'a self, document: &mut Report<'a>) {
+        document.cardinality = Some(report::cardinality_report(self));
+    }
+
+    fn write_tables(&self, format: OutputFormat, out: &mut dyn Write) -> io::Result<()> {
+        if self.overall.is_empty() {
+            return Ok(());
+        }
+
+        // Fields no entry had are left out.
+        let fields = self.fields_seen();
+
+        let table = fields
+            .iter()
+            .map(|field| CardinalityTableEntry {
+                Field: field,
+                Values: self.overall[*field].to_string(),
+            })
+            .collect();
+        writeln!(
+            out,
+            "{}",
+            report::table(format, "Distinct values per field (~ estimated)", table)
+        )?;
+
+        // One column per field, which a Tabled struct cannot have.
+        let headers = std::iter::once("Process")
+            .chain(fields.iter().map(|f| f.as_str()))
+            .map(Cow::Borrowed)
+            .collect();
+        let rows = self
+            .per_process()
+            .into_iter()
+            .map(|(process, counts)| {
+                std::iter::once(Cow::Borrowed(process.as_str()))
+                    .chain(fields.iter().map(|field| {
+                        Cow::Owned(
+                            counts
+                                .get(*field)
+                                .map_or("-".to_string(), |d| d.to_string()),
+                        )
+                    }))
+                    .collect()
+            })
+            .collect();
+        writeln!(
+            out,
+            "{}",
+            report::text_table(format, "Distinct values per process", headers, rows)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(fields: &[(&str, String)]) -> Entry {
+        fields
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.clone()))
+            .collect()
+    }
+
+    fn message(process: &str) -> Message {
+        Message {
+            msg: String::new(),
+            process: process.to_string(),
+            priority: "6".to_string(),
+        }
+    }
+
+    #[test]
+    fn counts_small_sets_exactly() {
+        let mut cardinality = Cardinality::new(vec!["MESSAGE".to_string(), "_PID".to_string()]);
+        let mut fork = cardinality.fork();
+        for i in 0..10 {
+            let msg = format!("request {} done", i % 4);
+            let entry = entry(&[("MESSAGE", msg), ("_PID", "1".into())]);
+            match i % 2 {
+                0 => cardinality.observe(&message("app"), &entry),
+                _ => fork.observe(&message("app"), &entry),
+            }
+        }
+        fork.observe(&message("cron"), &entry(&[("MESSAGE", "tick".into())]));
+        cardinality.merge(fork).unwrap();
+
+        let message = &cardinality.overall()["MESSAGE"];
+        assert!(message.is_exact());
+        assert_eq!(message.count(), 5);
+        assert_eq!(cardinality.overall()["_PID"].count(), 1);
+
+        let per_process = cardinality.per_process();
+        assert_eq!(per_process[0].0, "app");
+        assert_eq!(per_process[0].1["MESSAGE"].count(), 4);
+        assert!(!per_process[1].1.contains_key("_PID"));
+    }
+
+    #[test]
+    fn estimates_large_sets_and_merges() {
+        let (mut left, mut right) = (Distinct::default(), Distinct::default());
+        let mut all = Distinct::default();
+        for i in 0..100_000 {
+            let value = format!("id={}", i);
+            // Overlapping halves, 75% of the values on each side.
+            if i % 4 != 0 {
+                left.insert(&value);
+            }
+            if i % 4 != 1 {
+                right.insert(&value);
+            }
+            all.insert(&value);
+        }
+
+        assert!(!all.is_exact());
+        let error = (all.count() as f64 - 100_000.0).abs() / 100_000.0;
+        assert!(error < 0.05, "estimated {}", all.count());
+
+        let mut small = Distinct::default();
+        small.insert("id=1");
+        small.merge(left);
+        small.merge(right);
+        assert_eq!(small, all);
+    }
+}
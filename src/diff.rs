@@ -0,0 +1,166 @@
+//! Comparison of the message counts of two journals or time windows.
+
+use crate::Message;
+use std::{cmp::Ordering, collections::HashMap, hash::Hash};
+
+/// How the count of one process or message changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change<K> {
+    pub key: K,
+    pub before: u32,
+    pub after: u32,
+}
+
+impl<K> Change<K> {
+    /// The change in count, negative when fewer were logged.
+    pub fn delta(&self) -> i64 {
+        self.after as i64 - self.before as i64
+    }
+
+    /// The count after as a multiple of the count before, `None` when there
+    /// was none before.
+    pub fn ratio(&self) -> Option<f64> {
+        (self.before > 0).then(|| self.after as f64 / self.before as f64)
+    }
+
+    /// Size of the relative change, a halving ranking the same as a doubling.
+    fn factor(&self) -> f64 {
+        match self.ratio() {
+            Some(r) if r > 0.0 => r.max(1.0 / r),
+            _ => f64::INFINITY,
+        }
+    }
+}
+
+/// Every key counted on either side, in key order.
+fn changes<'a, K: Hash + Ord>(
+    before: &'a HashMap<K, u32>,
+    after: &'a HashMap<K, u32>,
+) -> Vec<Change<&'a K>> {
+    let mut keys: Vec<&K> = before.keys().chain(after.keys()).collect();
+    keys.sort();
+    keys.dedup();
+
+    keys.into_iter()
+        .map(|key| Change {
+            key,
+            before: before.get(key).copied().unwrap_or(0),
+            after: after.get(key).copied().unwrap_or(0),
+        })
+        .collect()
+}
+
+/// Largest absolute change first.
+fn by_delta<K>(a: &Change<K>, b: &Change<K>) -> Ordering {
+    b.delta().abs().cmp(&a.delta().abs())
+}
+
+/// Sort by `cmp` then key, and keep the first `n`.
+fn top<K: Ord>(
+    mut changes: Vec<Change<K>>,
+    n: usize,
+    cmp: impl Fn(&Change<K>, &Change<K>) -> Ordering,
+) -> Vec<Change<K>> {
+    changes.sort_by(|a, b| cmp(a, b).then_with(|| a.key.cmp(&b.key)));
+    changes.truncate(n);
+    changes
+}
+
+/// The differences between two sets of statistics.
+#[derive(Debug)]
+pub struct Diff<'a> {
+    /// Every process, largest absolute change first.
+    pub processes: Vec<Change<&'a String>>,
+    /// Messages only logged after, most frequent first.
+    pub new: Vec<Change<&'a Message>>,
+    /// Messages only logged before, most frequent first.
+    pub vanished: Vec<Change<&'a Message>>,
+    /// Messages logged on both sides, largest absolute change first.
+    pub absolute: Vec<Change<&'a Message>>,
+    /// Messages logged on both sides, largest relative change first.
+    pub relative: Vec<Change<&'a Message>>,
+}
+
+impl<'a> Diff<'a> {
+    /// Compare per message and per process counts, keeping `n` messages in
+    /// each list.
+    pub fn new(
+        before: (&'a HashMap<Message, u32>, &'a HashMap<String, u32>),
+        after: (&'a HashMap<Message, u32>, &'a HashMap<String, u32>),
+        n: usize,
+    ) -> Self {
+        let processes = top(changes(before.1, after.1), usize::MAX, by_delta);
+
+        let (mut new, mut vanished, mut both) = (Vec::new(), Vec::new(), Vec::new());
+        for change in changes(before.0, after.0) {
+            match (change.before, change.after) {
+                (0, _) => new.push(change),
+                (_, 0) => vanished.push(change),
+                _ if change.delta() != 0 => both.push(change),
+                _ => {}
+            }
+        }
+
+        Diff {
+            processes,
+            new: top(new, n, |a, b| b.after.cmp(&a.after)),
+            vanished: top(vanished, n, |a, b| b.before.cmp(&a.before)),
+            absolute: top(both.clone(), n, by_delta),
+            relative: top(both, n, |a, b| {
+                b.factor()
+                    .total_cmp(&a.factor())
+                    .then_with(|| by_delta(a, b))
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn msg(msg: &str, process: &str) -> Message {
+        Message {
+            msg: msg.to_string(),
+            process: process.to_string(),
+            priority: "6".to_string(),
+        }
+    }
+
+    fn counts<K: Hash + Eq + Clone>(counts: &[(K, u32)]) -> HashMap<K, u32> {
+        counts.iter().cloned().collect()
+    }
+
+    #[test]
+    fn finds_new_vanished_and_changed_messages() {
+        let before = counts(&[
+            (msg("steady", "app"), 10),
+            (msg("doubled", "app"), 5),
+            (msg("grown", "app"), 100),
+            (msg("gone", "cron"), 3),
+        ]);
+        let after = counts(&[
+            (msg("steady", "app"), 10),
+            (msg("doubled", "app"), 10),
+            (msg("grown", "app"), 150),
+            (msg("fresh", "cron"), 7),
+        ]);
+        let pp_before = counts(&[("app".to_string(), 115), ("cron".to_string(), 3)]);
+        let pp_after = counts(&[("app".to_string(), 170), ("cron".to_string(), 7)]);
+
+        let diff = Diff::new((&before, &pp_before), (&after, &pp_after), 10);
+
+        let keys = |changes: &[Change<&Message>]| -> Vec<String> {
+            changes.iter().map(|c| c.key.msg.clone()).collect()
+        };
+        assert_eq!(keys(&diff.new), vec!["fresh"]);
+        assert_eq!(keys(&diff.vanished), vec!["gone"]);
+        assert_eq!(keys(&diff.absolute), vec!["grown", "doubled"]);
+        assert_eq!(keys(&diff.relative), vec!["doubled", "grown"]);
+        assert_eq!(diff.relative[0].ratio(), Some(2.0));
+
+        assert_eq!(diff.processes[0].key.as_str(), "app");
+        assert_eq!(diff.processes[0].delta(), 55);
+        assert_eq!(diff.processes[1].delta(), 4);
+    }
+}
@@ -0,0 +1,54 @@
+//! Errors returned by the library.
+
+use std::{fmt, io, path::PathBuf};
+
+/// Anything that can go wrong reading a journal or writing a report.
+#[derive(Debug)]
+pub enum Error {
+    /// Reading the journal or writing the report failed.
+    Io(io::Error),
+    /// The input could not be opened.
+    Open { path: PathBuf, source: io::Error },
+    /// An option or argument was not usable.
+    Invalid(String),
+    /// The JSON report could not be written.
+    Json(serde_json::Error),
+}
+
+/// Result type of the library.
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Open { path, source } => {
+                write!(f, "cannot open {}: {}", path.display(), source)
+            }
+            Error::Invalid(message) => write!(f, "{}", message),
+            Error::Json(e) => write!(f, "cannot write JSON report: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) | Error::Open { source: e, .. } => Some(e),
+            Error::Invalid(_) => None,
+            Error::Json(e) => Some(e),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Json(e)
+    }
+}
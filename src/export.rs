@@ -0,0 +1,146 @@
+//! Reader for the Journal Export Format, as written by `journalctl -o export`.
+//!
+//! Entries are separated by an empty line. Each field is either a
+//! `NAME=value` line, or for values that are not plain text, the field name on
+//! its own line followed by a little endian 64 bit length, the raw value and a
+//! newline. See https://systemd.io/JOURNAL_EXPORT_FORMATS/.
+
+use crate::source::{Entry, EntrySource};
+use std::io::{self, BufRead, Read};
+
+pub struct ExportReader<R: BufRead> {
+    reader: R,
+    line: Vec<u8>,
+}
+
+impl<R: BufRead> ExportReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            line: Vec::new(),
+        }
+    }
+
+    /// Read the length prefixed value of a binary field.
+    fn read_binary(&mut self, name: &str) -> io::Result<String> {
+        let mut len = [0u8; 8];
+        self.reader.read_exact(&mut len)?;
+
+        // The length comes from the input, so the value only grows as far as
+        // the input goes rather than being allocated up front.
+        let len = u64::from_le_bytes(len);
+        let mut value = Vec::new();
+        if self.reader.by_ref().take(len).read_to_end(&mut value)? as u64 != len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "binary field {} is shorter than its length of {}",
+                    name, len
+                ),
+            ));
+        }
+
+        let mut newline = [0u8; 1];
+        self.reader.read_exact(&mut newline)?;
+        if newline[0] != b'\n' {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("missing newline after binary field {}", name),
+            ));
+        }
+
+        Ok(String::from_utf8_lossy(&value).into())
+    }
+}
+
+impl<R: BufRead> EntrySource for ExportReader<R> {
+    fn next_entry(&mut self) -> io::Result<Option<Entry>> {
+        let mut entry = Entry::new();
+
+        loop {
+            self.line.clear();
+            if self.reader.read_until(b'\n', &mut self.line)? == 0 {
+                // End of input, the final entry need not be terminated.
+                return Ok(if entry.is_empty() { None } else { Some(entry) });
+            }
+
+            if self.line.last() == Some(&b'\n') {
+                self.line.pop();
+            }
+
+            if self.line.is_empty() {
+                if entry.is_empty() {
+                    continue;
+                }
+                return Ok(Some(entry));
+            }
+
+            match self.line.iter().position(|&b| b == b'=') {
+                Some(eq) => {
+                    entry.insert(
+                        String::from_utf8_lossy(&self.line[..eq]).into(),
+                        String::from_utf8_lossy(&self.line[eq + 1..]).into(),
+                    );
+                }
+                None => {
+                    let name: String = String::from_utf8_lossy(&self.line).into();
+                    let value = self.read_binary(&name)?;
+                    entry.insert(name, value);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_all(input: &[u8]) -> io::Result<Vec<Entry>> {
+        let mut reader = ExportReader::new(input);
+        let mut entries = Vec::new();
+        while let Some(entry) = reader.next_entry()? {
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+
+    #[test]
+    fn reads_text_and_binary_fields() {
+        let mut input = b"__REALTIME_TIMESTAMP=1\nMESSAGE=first\n_COMM=sshd\n\n".to_vec();
+        input.extend_from_slice(b"MESSAGE\n");
+        input.extend_from_slice(&13u64.to_le_bytes());
+        input.extend_from_slice(b"line1\nline2=x\n");
+        input.extend_from_slice(b"_COMM=cron\n");
+
+        let entries = read_all(&input).unwrap();
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0]["MESSAGE"], "first");
+        assert_eq!(entries[0]["__REALTIME_TIMESTAMP"], "1");
+        assert_eq!(entries[1]["MESSAGE"], "line1\nline2=x");
+        assert_eq!(entries[1]["_COMM"], "cron");
+    }
+
+    #[test]
+    fn truncated_binary_field_is_an_error() {
+        let mut input = b"MESSAGE\n".to_vec();
+        input.extend_from_slice(&100u64.to_le_bytes());
+        input.extend_from_slice(b"short");
+
+        assert!(read_all(&input).is_err());
+    }
+
+    #[test]
+    fn plain_text_is_an_error() {
+        // Lines without '=' read as binary fields, with a length taken from
+        // the next line's bytes.
+        let err = read_all(
+            b"hello world
+this is not an export
+",
+        )
+        .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,344 @@
+//! Prometheus exporter, counters kept up to date while following the journal
+//! and served over HTTP in the text exposition format.
+//!
+//! Label values come from the journal, so the number of series is bounded:
+//! past `MAX_SERIES` process and unit pairs, new ones are counted under
+//! "other", and likewise for templates past `MAX_TEMPLATES`. Only the most
+//! frequent templates are served.
+
+use crate::{
+    footprint::{self, NO_UNIT},
+    priority,
+    source::{self, Entry},
+    template::Masker,
+};
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    io::{self, BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+/// Most process and unit pairs given their own series.
+const MAX_SERIES: usize = 500;
+
+/// Most templates counted on their own.
+const MAX_TEMPLATES: usize = 1000;
+
+/// Label value standing in for everything past the limits.
+const OTHER: &str = "other";
+
+/// Content type of the text exposition format.
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// How long a scraper gets to send its request.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Default)]
+struct State {
+    // Entries per process, unit and priority name.
+    messages: HashMap<(String, String, String), u64>,
+    // Payload bytes per process and unit.
+    bytes: HashMap<(String, String), u64>,
+    // Entries per process and masked message.
+    templates: HashMap<(String, String), u64>,
+    // Timestamp of the latest entry, in microseconds since the epoch.
+    last_entry: Option<u64>,
+}
+
+/// The counters served, shared between the reader and the HTTP thread.
+#[derive(Clone)]
+pub struct Metrics {
+    state: Arc<Mutex<State>>,
+    masker: Arc<Masker>,
+    top_templates: usize,
+}
+
+impl Metrics {
+    /// Create counters serving the `top_templates` most frequent templates.
+    pub fn new(top_templates: usize) -> Self {
+        Self {
+            state: Arc::default(),
+            masker: Arc::new(Masker::default()),
+            top_templates,
+        }
+    }
+
+    /// Count an entry logged by `process`.
+    pub fn observe(&self, process: &str, priority: &str, msg: &str, entry: &Entry) {
+        let template = self.masker.mask(msg);
+        let size: u64 = entry
+            .iter()
+            .filter(|(name, _)| !name.starts_with("__"))
+            .map(|(name, value)| footprint::field_size(name, value))
+            .sum();
+        let unit = entry.get("_SYSTEMD_UNIT").map_or(NO_UNIT, |u| u.as_str());
+        let priority = priority::name(priority).unwrap_or(priority);
+
+        let mut state = self.state.lock().unwrap();
+
+        let mut series = (process.to_string(), unit.to_string());
+        if !state.bytes.contains_key(&series) && state.bytes.len() >= MAX_SERIES {
+            series = (OTHER.to_string(), OTHER.to_string());
+        }
+
+        let mut key = (series.0.clone(), template);
+        if !state.templates.contains_key(&key) && state.templates.len() >= MAX_TEMPLATES {
+            key.1 = OTHER.to_string();
+        }
+        *state.templates.entry(key).or_insert(0) += 1;
+
+        *state
+            .messages
+            .entry((series.0.clone(), series.1.clone(), priority.to_string()))
+            .or_insert(0) += 1;
+        *state.bytes.entry(series).or_insert(0) += size;
+
+        if let Some(ts) = source::timestamp(entry) {
+            state.last_entry = Some(state.last_entry.map_or(ts, |last| last.max(ts)));
+        }
+    }
+
+    /// The metrics in the text exposition format.
+    pub fn render(&self) -> String {
+        let state = self.state.lock().unwrap();
+        let mut out = String::new();
+
+        let mut messages: Vec<_> = state.messages.iter().collect();
+        messages.sort();
+        header(
+            &mut out,
+            "journalstat_messages_total",
+            "counter",
+            "Journal entries read, by process, unit and priority.",
+        );
+        for ((process, unit, priority), count) in messages {
+            sample(
+                &mut out,
+                "journalstat_messages_total",
+                &[("process", process), ("unit", unit), ("priority", priority)],
+                count,
+            );
+        }
+
+        let mut bytes: Vec<_> = state.bytes.iter().collect();
+        bytes.sort();
+        header(
+            &mut out,
+            "journalstat_bytes_total",
+            "counter",
+            "Payload bytes of the journal entries read, by process and unit.",
+        );
+        for ((process, unit), size) in bytes {
+            sample(
+                &mut out,
+                "journalstat_bytes_total",
+                &[("process", process), ("unit", unit)],
+                size,
+            );
+        }
+
+        let mut templates: Vec<_> = state.templates.iter().collect();
+        templates.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+        templates.truncate(self.top_templates);
+        templates.sort();
+        header(
+            &mut out,
+            "journalstat_template_messages_total",
+            "counter",
+            "Journal entries read for the most frequent message templates.",
+        );
+        for ((process, template), count) in templates {
+            sample(
+                &mut out,
+                "journalstat_template_messages_total",
+                &[("process", process), ("template", template)],
+                count,
+            );
+        }
+
+        if let Some(last) = state.last_entry {
+            header(
+                &mut out,
+                "journalstat_last_entry_timestamp_seconds",
+                "gauge",
+                "Time of the latest journal entry read.",
+            );
+            sample(
+                &mut out,
+                "journalstat_last_entry_timestamp_seconds",
+                &[],
+                last as f64 / 1e6,
+            );
+        }
+
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn sample(out: &mut String, name: &str, labels: &[(&str, &str)], value: impl std::fmt::Display) {
+    out.push_str(name);
+    if !labels.is_empty() {
+        let labels: Vec<String> = labels
+            .iter()
+            .map(|(label, value)| format!("{}=\"{}\"", label, escape(value)))
+            .collect();
+        let _ = write!(out, "{{{}}}", labels.join(","));
+    }
+    let _ = writeln!(out, " {}", value);
+}
+
+/// Escape a label value, backslashes, quotes and line feeds being special.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Serve `metrics` on `listener` from a new thread, one connection at a time.
+/// A connection that fails is passed to `on_error` and the next one served.
+pub fn serve(
+    listener: TcpListener,
+    metrics: Metrics,
+    mut on_error: impl FnMut(io::Error) + Send + 'static,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            if let Err(e) = stream.and_then(|stream| respond(stream, &metrics)) {
+                on_error(e);
+            }
+        }
+    })
+}
+
+/// Answer a single HTTP request.
+fn respond(mut stream: TcpStream, metrics: &Metrics) -> io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+
+    let mut reader = BufReader::new(&stream);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    // The headers are not needed, only read past them.
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 0 && !line.trim_end().is_empty() {
+        line.clear();
+    }
+
+    let mut parts = request.split_whitespace();
+    let method = parts.next().unwrap_or("");
+    let path = parts.next().unwrap_or("").split('?').next().unwrap_or("");
+
+    let (status, content_type, body) = match (method, path) {
+        ("GET", "/metrics") => ("200 OK", CONTENT_TYPE, metrics.render()),
+        ("GET", "/") => (
+            "200 OK",
+            "text/html; charset=utf-8",
+            "<html><body><a href=\"/metrics\">Metrics</a></body></html>\n".to_string(),
+        ),
+        ("GET", _) => (
+            "404 Not Found",
+            "text/plain; charset=utf-8",
+            "not found\n".to_string(),
+        ),
+        _ => (
+            "405 Method Not Allowed",
+            "text/plain; charset=utf-8",
+            "method not allowed\n".to_string(),
+        ),
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn entry(fields: &[(&str, &str)]) -> Entry {
+        fields
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn renders_escaped_and_bounded_series() {
+        let metrics = Metrics::new(1);
+        let e = entry(&[
+            ("MESSAGE", "job 1 done"),
+            ("_SYSTEMD_UNIT", "app.service"),
+            ("__REALTIME_TIMESTAMP", "1500000"),
+        ]);
+        metrics.observe("app", "6", "job 1 done", &e);
+        metrics.observe("app", "6", "job 2 done", &e);
+        metrics.observe("a\"b\\c\nd", "3", "odd", &entry(&[("MESSAGE", "odd")]));
+
+        let out = metrics.render();
+        assert!(out.contains(
+            "journalstat_messages_total{process=\"app\",unit=\"app.service\",priority=\"info\"} 2\n"
+        ));
+        assert!(out.contains(
+            "journalstat_messages_total{process=\"a\\\"b\\\\c\\nd\",unit=\"unknown\",priority=\"err\"} 1\n"
+        ));
+        assert!(out.contains("journalstat_bytes_total{process=\"app\",unit=\"app.service\"} 86\n"));
+        assert!(out.contains(
+            "journalstat_template_messages_total{process=\"app\",template=\"job <NUM> done\"} 2\n"
+        ));
+        assert!(!out.contains("template=\"odd\""));
+        assert!(out.contains("# TYPE journalstat_messages_total counter\n"));
+        assert!(out.contains("journalstat_last_entry_timestamp_seconds 1.5\n"));
+
+        let metrics = Metrics::new(0);
+        for i in 0..MAX_SERIES + 2 {
+            metrics.observe(&format!("p{}", i), "6", "hi", &entry(&[]));
+        }
+        let state = metrics.state.lock().unwrap();
+        assert_eq!(state.bytes.len(), MAX_SERIES + 1);
+        assert_eq!(
+            state.messages[&(OTHER.to_string(), OTHER.to_string(), "info".to_string())],
+            2
+        );
+    }
+
+    #[test]
+    fn serves_metrics_over_http() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let metrics = Metrics::new(10);
+        metrics.observe("app", "6", "hi", &entry(&[("MESSAGE", "hi")]));
+        serve(listener, metrics, |e| panic!("{}", e));
+
+        let get = |path: &str| {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+
+        let response = get("/metrics");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains(&format!("Content-Type: {}\r\n", CONTENT_TYPE)));
+        assert!(response
+            .ends_with("journalstat_template_messages_total{process=\"app\",template=\"hi\"} 1\n"));
+        assert!(get("/nope").starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+}
@@ -0,0 +1,146 @@
+//! Fallbacks for entries lacking `_COMM` or `PRIORITY`, such as kernel
+//! messages, some entries received over syslog and output captured from
+//! services, so that they are counted rather than dropped.
+//!
+//! The process is taken from the first of `_COMM`, `SYSLOG_IDENTIFIER`,
+//! "kernel" for entries with `_TRANSPORT=kernel` and `_SYSTEMD_UNIT`, and the
+//! priority defaults to [`DEFAULT_PRIORITY`]. Entries without a `MESSAGE` or
+//! any of the process fields are skipped. Both are counted by reason.
+
+use crate::source::Entry;
+use serde::Serialize;
+
+/// Priority of entries without one, info as journald assumes for output
+/// captured from services.
+pub const DEFAULT_PRIORITY: &str = "6";
+
+/// Process counted for kernel messages without a `_COMM` or
+/// `SYSLOG_IDENTIFIER`.
+pub const KERNEL: &str = "kernel";
+
+/// Where the process of an entry was taken from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessField {
+    Comm,
+    SyslogIdentifier,
+    Kernel,
+    Unit,
+}
+
+/// The process that logged `entry` and the field naming it, if any does.
+pub fn process(entry: &Entry) -> Option<(&str, ProcessField)> {
+    if let Some(comm) = entry.get("_COMM") {
+        Some((comm, ProcessField::Comm))
+    } else if let Some(identifier) = entry.get("SYSLOG_IDENTIFIER") {
+        Some((identifier, ProcessField::SyslogIdentifier))
+    } else if entry.get("_TRANSPORT").is_some_and(|t| t == "kernel") {
+        Some((KERNEL, ProcessField::Kernel))
+    } else {
+        entry
+            .get("_SYSTEMD_UNIT")
+            .map(|unit| (unit.as_str(), ProcessField::Unit))
+    }
+}
+
+/// Entries counted with a fallback, and entries skipped, by reason.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct Fallbacks {
+    pub process_from_syslog_identifier: u64,
+    pub process_from_kernel_transport: u64,
+    pub process_from_unit: u64,
+    pub default_priority: u64,
+    pub skipped_no_message: u64,
+    /// Entries without `_COMM`, `SYSLOG_IDENTIFIER`, kernel transport or
+    /// `_SYSTEMD_UNIT`.
+    pub skipped_no_process: u64,
+}
+
+impl Fallbacks {
+    /// Count an entry counted under a process taken from `field`, with or
+    /// without a priority of its own.
+    pub fn observe(&mut self, field: ProcessField, has_priority: bool) {
+        match field {
+            ProcessField::Comm => {}
+            ProcessField::SyslogIdentifier => self.process_from_syslog_identifier += 1,
+            ProcessField::Kernel => self.process_from_kernel_transport += 1,
+            ProcessField::Unit => self.process_from_unit += 1,
+        }
+        if !has_priority {
+            self.default_priority += 1;
+        }
+    }
+
+    /// Add the entries counted by `other`.
+    pub fn merge(&mut self, other: Fallbacks) {
+        self.process_from_syslog_identifier += other.process_from_syslog_identifier;
+        self.process_from_kernel_transport += other.process_from_kernel_transport;
+        self.process_from_unit += other.process_from_unit;
+        self.default_priority += other.default_priority;
+        self.skipped_no_message += other.skipped_no_message;
+        self.skipped_no_process += other.skipped_no_process;
+    }
+
+    /// Every reason entries were counted with a fallback or skipped for, and
+    /// their number, leaving out those without entries.
+    pub fn reasons(&self) -> Vec<(&'static str, u64)> {
+        [
+            (
+                "No _COMM, process from SYSLOG_IDENTIFIER",
+                self.process_from_syslog_identifier,
+            ),
+            (
+                "No _COMM, process \"kernel\" from _TRANSPORT",
+                self.process_from_kernel_transport,
+            ),
+            (
+                "No _COMM, process from _SYSTEMD_UNIT",
+                self.process_from_unit,
+            ),
+            ("No PRIORITY, counted as info", self.default_priority),
+            ("Skipped, no MESSAGE", self.skipped_no_message),
+            (
+                "Skipped, no field naming the process",
+                self.skipped_no_process,
+            ),
+        ]
+        .into_iter()
+        .filter(|(_, entries)| *entries > 0)
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(fields: &[(&str, &str)]) -> Entry {
+        fields
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn process_falls_back_in_order() {
+        let all = [
+            ("_COMM", "sshd"),
+            ("SYSLOG_IDENTIFIER", "sshd-session"),
+            ("_TRANSPORT", "syslog"),
+            ("_SYSTEMD_UNIT", "ssh.service"),
+        ];
+        assert_eq!(process(&entry(&all)), Some(("sshd", ProcessField::Comm)));
+        assert_eq!(
+            process(&entry(&all[1..])),
+            Some(("sshd-session", ProcessField::SyslogIdentifier))
+        );
+        assert_eq!(
+            process(&entry(&all[2..])),
+            Some(("ssh.service", ProcessField::Unit))
+        );
+        assert_eq!(
+            process(&entry(&[("_TRANSPORT", "kernel"), ("_SYSTEMD_UNIT", "x")])),
+            Some((KERNEL, ProcessField::Kernel))
+        );
+        assert_eq!(process(&entry(&[("_TRANSPORT", "stdout")])), None);
+    }
+}
@@ -0,0 +1,213 @@
+//! Field match expressions, as taken by `--match`.
+//!
+//! Each term compares one field of an entry:
+//!
+//!   * `FIELD=VALUE`   the field equals VALUE.
+//!   * `FIELD!=VALUE`  the field is missing or does not equal VALUE.
+//!   * `FIELD~REGEX`   the field matches REGEX.
+//!   * `FIELD!~REGEX`  the field is missing or does not match REGEX.
+//!
+//! As with journalctl, terms are ANDed together except that `FIELD=VALUE`
+//! terms on the same field are ORed, and a `+` between terms ORs the groups
+//! either side of it.
+
+use crate::source::Entry;
+use regex::Regex;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone)]
+enum Op {
+    Eq(String),
+    Ne(String),
+    Match(Regex),
+    NotMatch(Regex),
+}
+
+#[derive(Debug, Clone)]
+struct Term {
+    field: String,
+    op: Op,
+}
+
+impl Term {
+    fn parse(s: &str) -> Result<Self, String> {
+        let name_len = s
+            .find(|c: char| !(c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_'))
+            .unwrap_or(s.len());
+        let (field, rest) = s.split_at(name_len);
+        if field.is_empty() {
+            return Err(format!("invalid match '{}', expected a field name", s));
+        }
+
+        let regex = |re: &str| Regex::new(re).map_err(|e| format!("invalid match '{}': {}", s, e));
+        let op = if let Some(value) = rest.strip_prefix("!=") {
+            Op::Ne(value.to_string())
+        } else if let Some(re) = rest.strip_prefix("!~") {
+            Op::NotMatch(regex(re)?)
+        } else if let Some(value) = rest.strip_prefix('=') {
+            Op::Eq(value.to_string())
+        } else if let Some(re) = rest.strip_prefix('~') {
+            Op::Match(regex(re)?)
+        } else {
+            return Err(format!(
+                "invalid match '{}', expected FIELD=VALUE, FIELD!=VALUE, FIELD~REGEX or FIELD!~REGEX",
+                s
+            ));
+        };
+
+        Ok(Term {
+            field: field.to_string(),
+            op,
+        })
+    }
+
+    fn matches(&self, entry: &Entry) -> bool {
+        let value = entry.get(&self.field);
+        match &self.op {
+            Op::Eq(v) => value == Some(v),
+            Op::Ne(v) => value != Some(v),
+            Op::Match(re) => value.is_some_and(|value| re.is_match(value)),
+            Op::NotMatch(re) => !value.is_some_and(|value| re.is_match(value)),
+        }
+    }
+}
+
+/// A disjunction of groups of terms.
+#[derive(Debug, Clone)]
+pub struct MatchExpr {
+    groups: Vec<Vec<Term>>,
+    // The terms as given, for reporting.
+    terms: Vec<String>,
+}
+
+impl MatchExpr {
+    /// Build an expression from its terms and `+` separators, one per argument.
+    pub fn parse<S: AsRef<str>>(args: &[S]) -> Result<Self, String> {
+        let mut groups = vec![Vec::new()];
+
+        for arg in args {
+            let arg = arg.as_ref();
+            if arg == "+" {
+                groups.push(Vec::new());
+            } else {
+                groups.last_mut().unwrap().push(Term::parse(arg)?);
+            }
+        }
+
+        if groups.iter().any(|g| g.is_empty()) {
+            return Err("'+' must separate two groups of matches".to_string());
+        }
+
+        Ok(MatchExpr {
+            groups,
+            terms: args.iter().map(|a| a.as_ref().to_string()).collect(),
+        })
+    }
+
+    /// The terms and `+` separators the expression was built from.
+    pub fn terms(&self) -> &[String] {
+        &self.terms
+    }
+
+    /// Whether an entry satisfies the expression.
+    pub fn matches(&self, entry: &Entry) -> bool {
+        self.groups.iter().any(|group| {
+            // Equality terms on the same field are alternatives.
+            let mut alternatives: BTreeMap<&str, bool> = BTreeMap::new();
+            for term in group {
+                if let Op::Eq(_) = term.op {
+                    *alternatives.entry(&term.field).or_default() |= term.matches(entry);
+                } else if !term.matches(entry) {
+                    return false;
+                }
+            }
+
+            alternatives.values().all(|m| *m)
+        })
+    }
+
+    /// The `FIELD=VALUE` terms of each group, for sources that can filter on
+    /// them natively. Each group's terms select a superset of the entries the
+    /// group matches, so `None` is returned if any group has no such terms.
+    pub fn equality_groups(&self) -> Option<Vec<Vec<(&str, &str)>>> {
+        self.groups
+            .iter()
+            .map(|group| {
+                let terms: Vec<(&str, &str)> = group
+                    .iter()
+                    .filter_map(|term| match &term.op {
+                        Op::Eq(value) => Some((term.field.as_str(), value.as_str())),
+                        _ => None,
+                    })
+                    .collect();
+                (!terms.is_empty()).then_some(terms)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(fields: &[(&str, &str)]) -> Entry {
+        fields
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn expr(args: &[&str]) -> MatchExpr {
+        MatchExpr::parse(args).unwrap()
+    }
+
+    #[test]
+    fn equality_terms_on_one_field_are_ored() {
+        let e = expr(&["_COMM=sshd", "_COMM=cron", "_UID=0"]);
+
+        assert!(e.matches(&entry(&[("_COMM", "cron"), ("_UID", "0")])));
+        assert!(!e.matches(&entry(&[("_COMM", "cron"), ("_UID", "1000")])));
+        assert!(!e.matches(&entry(&[("_COMM", "bash"), ("_UID", "0")])));
+    }
+
+    #[test]
+    fn negation_regex_and_disjunction() {
+        let e = expr(&[
+            "SYSLOG_IDENTIFIER~^app-",
+            "_HOSTNAME!=build",
+            "+",
+            "_TRANSPORT=kernel",
+        ]);
+
+        assert!(e.matches(&entry(&[("SYSLOG_IDENTIFIER", "app-web")])));
+        assert!(!e.matches(&entry(&[
+            ("SYSLOG_IDENTIFIER", "app-web"),
+            ("_HOSTNAME", "build")
+        ])));
+        assert!(!e.matches(&entry(&[("SYSLOG_IDENTIFIER", "web")])));
+        assert!(e.matches(&entry(&[("_TRANSPORT", "kernel")])));
+        assert!(expr(&["MSG!~x"]).matches(&entry(&[])));
+    }
+
+    #[test]
+    fn rejects_bad_expressions() {
+        assert!(MatchExpr::parse(&["comm=sshd"]).is_err());
+        assert!(MatchExpr::parse(&["_COMM"]).is_err());
+        assert!(MatchExpr::parse(&["_COMM~("]).is_err());
+        assert!(MatchExpr::parse(&["+", "_COMM=sshd"]).is_err());
+    }
+
+    #[test]
+    fn equality_groups_for_push_down() {
+        let e = expr(&["_COMM=sshd", "_UID!=0", "+", "_TRANSPORT=kernel"]);
+        assert_eq!(
+            e.equality_groups(),
+            Some(vec![
+                vec![("_COMM", "sshd")],
+                vec![("_TRANSPORT", "kernel")]
+            ])
+        );
+
+        assert_eq!(expr(&["_COMM=a", "+", "_UID!=0"]).equality_groups(), None);
+    }
+}
@@ -0,0 +1,198 @@
+//! Storage footprint, the payload bytes of entries attributed to the process,
+//! unit and fields they came from.
+//!
+//! An entry's size is the sum of its fields stored as `FIELD=value`, the form
+//! journald writes them in before any compression or deduplication. Address
+//! fields such as `__CURSOR` or `__REALTIME_TIMESTAMP` are not stored as data
+//! and are not counted.
+
+use crate::{
+    analyzer::{self, Analyzer, Section},
+    report::{self, OutputFormat, Report},
+    source::Entry,
+    Message,
+};
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+};
+use tabled::Tabled;
+
+/// Key used for entries that do not have a `_SYSTEMD_UNIT` field.
+pub const NO_UNIT: &str = "unknown";
+
+/// Payload size of a single field.
+pub fn field_size(name: &str, value: &str) -> u64 {
+    (name.len() + 1 + value.len()) as u64
+}
+
+/// Bytes logged per process, unit and field name.
+#[derive(Debug, Default)]
+pub struct Footprint {
+    /// Bytes over all entries counted.
+    pub total: u64,
+    pub per_process: HashMap<String, u64>,
+    pub per_unit: HashMap<String, u64>,
+    pub per_field: HashMap<String, u64>,
+}
+
+#[derive(Tabled)]
+#[allow(non_snake_case)]
+struct FootprintTableEntry<'a> {
+    Rank: usize,
+    Name: &'a str,
+    Bytes: u64,
+    Percent: String,
+}
+
+impl Analyzer for Footprint {
+    fn name(&self) -> &str {
+        "footprint"
+    }
+
+    /// Attribute the size of an entry to the process that logged it.
+    fn observe(&mut self, message: &Message, entry: &Entry) {
+        let mut size = 0;
+
+        for (name, value) in entry.iter().filter(|(name, _)| !name.starts_with("__")) {
+            let field = field_size(name, value);
+            *self.per_field.entry(name.clone()).or_insert(0) += field;
+            size += field;
+        }
+
+        let unit = entry.get("_SYSTEMD_UNIT").map_or(NO_UNIT, |u| u.as_str());
+        *self.per_process.entry(message.process.clone()).or_insert(0) += size;
+        *self.per_unit.entry(unit.to_string()).or_insert(0) += size;
+        self.total += size;
+    }
+
+    fn fork(&self) -> Box<dyn Analyzer> {
+        Box::<Self>::default()
+    }
+
+    fn merge(&mut self, other: Box<dyn Analyzer>) -> crate::Result<()> {
+        let other = analyzer::downcast::<Self>(other)?;
+        for (mine, theirs) in [
+            (&mut self.per_process, other.per_process),
+            (&mut self.per_unit, other.per_unit),
+            (&mut self.per_field, other.per_field),
+        ] {
+            for (name, bytes) in theirs {
+                *mine.entry(name).or_insert(0) += bytes;
+            }
+        }
+        self.total += other.total;
+        Ok(())
+    }
+
+    fn finish(&self) -> Section {
+        Section {
+            name: self.name().to_string(),
+            title: format!("Bytes per process ({} total)", self.total),
+            columns: ["Rank", "Name", "Bytes", "Percent"]
+                .map(String::from)
+                .to_vec(),
+            rows: report::byte_shares(&self.per_process, self.total)
+                .into_iter()
+                .map(|share| {
+                    vec![
+                        share.rank.into(),
+                        share.name.into(),
+                        share.bytes.into(),
+                        ((share.percent * 100.0).round() / 100.0).into(),
+                    ]
+                })
+                .collect(),
+        }
+    }
+
+    fn report<'a>(&'a self, document: &mut Report<'a>) {
+        document.footprint = Some(report::FootprintReport {
+            total_bytes: self.total,
+            per_process: report::byte_shares(&self.per_process, self.total),
+            per_unit: report::byte_shares(&self.per_unit, self.total),
+            per_field: report::byte_shares(&self.per_field, self.total),
+        });
+    }
+
+    fn write_tables(&self, format: OutputFormat, out: &mut dyn Write) -> io::Result<()> {
+        if self.total == 0 {
+            return Ok(());
+        }
+
+        for (title, sizes) in [
+            ("Bytes per process", &self.per_process),
+            ("Bytes per unit", &self.per_unit),
+            ("Bytes per field", &self.per_field),
+        ] {
+            let table = ranked(sizes)
+                .into_iter()
+                .enumerate()
+                .map(|(i, (name, bytes))| FootprintTableEntry {
+                    Rank: i + 1,
+                    Name: name,
+                    Bytes: bytes,
+                    Percent: format!("{:.02}", bytes as f64 / self.total as f64 * 100.0),
+                })
+                .collect();
+
+            writeln!(
+                out,
+                "{}",
+                report::table(format, &format!("{} ({} total)", title, self.total), table)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Sizes largest first, ties broken by name.
+pub fn ranked(sizes: &HashMap<String, u64>) -> Vec<(&String, u64)> {
+    let mut ranked: Vec<(&String, u64)> = sizes.iter().map(|(k, v)| (k, *v)).collect();
+    ranked.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    ranked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(fields: &[(&str, &str)]) -> Entry {
+        fields
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn message(process: &str) -> Message {
+        Message {
+            msg: String::new(),
+            process: process.to_string(),
+            priority: "6".to_string(),
+        }
+    }
+
+    #[test]
+    fn attributes_all_field_bytes() {
+        let mut fp = Footprint::default();
+        fp.observe(
+            &message("app"),
+            &entry(&[
+                ("MESSAGE", "hi"),
+                ("_CMDLINE", "/usr/bin/app --verbose"),
+                ("_SYSTEMD_UNIT", "app.service"),
+                ("__REALTIME_TIMESTAMP", "1"),
+            ]),
+        );
+        fp.observe(&message("cron"), &entry(&[("MESSAGE", "tick")]));
+
+        assert_eq!(fp.total, 10 + 31 + 25 + 12);
+        assert_eq!(fp.per_process["app"], 66);
+        assert_eq!(fp.per_unit["unknown"], 12);
+        assert_eq!(fp.per_field["MESSAGE"], 22);
+        assert!(!fp.per_field.contains_key("__REALTIME_TIMESTAMP"));
+
+        let units = ranked(&fp.per_unit);
+        assert_eq!(units[0], (&"app.service".to_string(), 66));
+    }
+}
@@ -0,0 +1,208 @@
+//! Heavy hitters counted in bounded memory with the Space-Saving algorithm
+//! (Metwally, Agrawal and El Abbadi, 2005).
+//!
+//! At most `capacity` items are tracked. An item not tracked takes the place
+//! of the one with the lowest count and inherits that count, which becomes
+//! its error. Every count is then an upper bound on the true count and
+//! `count - error` a lower bound, and no error exceeds `total / capacity`.
+//! Any item occurring more than `total / capacity` times is tracked.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    hash::Hash,
+    num::NonZeroUsize,
+};
+
+/// The count of a tracked item.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Counter<K> {
+    pub item: K,
+    /// Upper bound on the number of times the item was seen.
+    pub count: u64,
+    /// Most the count may be over, the count the item took over on eviction.
+    pub error: u64,
+}
+
+/// Approximate counts of the most frequent items in a stream.
+#[derive(Debug, Clone)]
+pub struct SpaceSaving<K> {
+    capacity: usize,
+    counters: Vec<Counter<K>>,
+    // Counter index by item.
+    index: HashMap<K, usize>,
+    // Counter indices ordered by count, the first is evicted next.
+    by_count: BTreeSet<(u64, usize)>,
+    // Number of items seen.
+    total: u64,
+}
+
+impl<K: Hash + Eq + Clone + Ord> SpaceSaving<K> {
+    /// Track at most `capacity` items.
+    pub fn new(capacity: NonZeroUsize) -> Self {
+        Self {
+            capacity: capacity.get(),
+            counters: Vec::new(),
+            index: HashMap::new(),
+            by_count: BTreeSet::new(),
+            total: 0,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Number of items seen.
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Most any count may be over, at most `total / capacity`. Once every
+    /// counter is in use, an item not tracked may have been seen as many
+    /// times as the lowest count.
+    pub fn max_error(&self) -> u64 {
+        self.counters
+            .iter()
+            .map(|c| c.error)
+            .fold(self.untracked(), u64::max)
+    }
+
+    /// Most an item not tracked may have been seen.
+    fn untracked(&self) -> u64 {
+        if self.counters.len() < self.capacity {
+            0
+        } else {
+            self.by_count.first().map_or(0, |(count, _)| *count)
+        }
+    }
+
+    /// Count an occurrence of `item`.
+    pub fn add(&mut self, item: &K) {
+        self.total += 1;
+
+        if let Some(&i) = self.index.get(item) {
+            let counter = &mut self.counters[i];
+            self.by_count.remove(&(counter.count, i));
+            counter.count += 1;
+            self.by_count.insert((counter.count, i));
+        } else if self.counters.len() < self.capacity {
+            self.insert(item.clone(), 1, 0);
+        } else {
+            let (min, i) = self.by_count.pop_first().expect("capacity is not zero");
+            let counter = &mut self.counters[i];
+            self.index.remove(&counter.item);
+            *counter = Counter {
+                item: item.clone(),
+                count: min + 1,
+                error: min,
+            };
+            self.index.insert(item.clone(), i);
+            self.by_count.insert((min + 1, i));
+        }
+    }
+
+    fn insert(&mut self, item: K, count: u64, error: u64) {
+        let i = self.counters.len();
+        self.index.insert(item.clone(), i);
+        self.by_count.insert((count, i));
+        self.counters.push(Counter { item, count, error });
+    }
+
+    /// The tracked item's counter.
+    pub fn get(&self, item: &K) -> Option<&Counter<K>> {
+        self.index.get(item).map(|&i| &self.counters[i])
+    }
+
+    /// Every tracked counter, in no particular order.
+    pub fn counters(&self) -> &[Counter<K>] {
+        &self.counters
+    }
+
+    /// Add the counts of `other`, summarizing another part of the stream.
+    /// An item tracked on one side only may have been seen as many times as
+    /// the other side's lowest count there, which is added to its count and
+    /// error.
+    pub fn merge(&mut self, other: SpaceSaving<K>) {
+        let (mine, theirs) = (self.untracked(), other.untracked());
+
+        let mut merged: HashMap<K, Counter<K>> = HashMap::new();
+        for counter in self.counters.drain(..) {
+            let (count, error) = match other.get(&counter.item) {
+                Some(c) => (c.count, c.error),
+                None => (theirs, theirs),
+            };
+            merged.insert(
+                counter.item.clone(),
+                Counter {
+                    count: counter.count + count,
+                    error: counter.error + error,
+                    item: counter.item,
+                },
+            );
+        }
+        for counter in other.counters {
+            merged.entry(counter.item.clone()).or_insert(Counter {
+                count: counter.count + mine,
+                error: counter.error + mine,
+                item: counter.item,
+            });
+        }
+
+        let mut merged: Vec<Counter<K>> = merged.into_values().collect();
+        merged.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.item.cmp(&b.item)));
+        merged.truncate(self.capacity);
+
+        self.index.clear();
+        self.by_count.clear();
+        for counter in merged {
+            self.insert(counter.item, counter.count, counter.error);
+        }
+        self.total += other.total;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(capacity: usize, items: &str) -> SpaceSaving<char> {
+        let mut summary = SpaceSaving::new(NonZeroUsize::new(capacity).unwrap());
+        for item in items.chars() {
+            summary.add(&item);
+        }
+        summary
+    }
+
+    #[test]
+    fn counts_bound_the_true_counts() {
+        let items = "aaaaabbbcdaeafagaha";
+        let summary = summary(3, items);
+        assert_eq!(summary.total(), items.len() as u64);
+        assert!(summary.max_error() <= summary.total() / 3);
+
+        for counter in summary.counters() {
+            let true_count = items.chars().filter(|c| *c == counter.item).count() as u64;
+            assert!(counter.count >= true_count);
+            assert!(counter.count - counter.error <= true_count);
+        }
+        // 'a' occurs more than total / capacity times, so is tracked.
+        assert_eq!(summary.get(&'a').map(|c| c.count - c.error), Some(10));
+    }
+
+    #[test]
+    fn merged_counts_bound_the_true_counts() {
+        let (left, right) = ("aaaabbcde", "aabbbbbfgh");
+        let mut merged = summary(3, left);
+        merged.merge(summary(3, right));
+        assert_eq!(merged.total(), 19);
+        assert!(merged.counters().len() <= 3);
+
+        let all = format!("{}{}", left, right);
+        for counter in merged.counters() {
+            let true_count = all.chars().filter(|c| *c == counter.item).count() as u64;
+            assert!(counter.count >= true_count, "{:?}", counter);
+            assert!(counter.count - counter.error <= true_count, "{:?}", counter);
+        }
+        assert!(merged.get(&'a').is_some() && merged.get(&'b').is_some());
+    }
+}
@@ -0,0 +1,374 @@
+//! Message rates over time, entries counted in fixed intervals of their
+//! `__REALTIME_TIMESTAMP`.
+//!
+//! Buckets start at whole multiples of the interval since the epoch, so hour
+//! and day buckets line up with UTC hours and days.
+
+use crate::{
+    analyzer::{self, Analyzer, Section},
+    report::{self, OutputFormat, Report},
+    source::{self, Entry},
+    time, Message,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    io::{self, Write},
+    str::FromStr,
+};
+use tabled::Tabled;
+
+/// Characters of a sparkline, lowest to highest.
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+const USEC_PER_SEC: u64 = 1_000_000;
+
+/// Number of processes the rates are broken down for.
+const PROCESSES: usize = 5;
+
+/// Width of the bars, in characters.
+const WIDTH: usize = 50;
+
+/// Most empty buckets listed between two counted ones. Longer gaps, such as
+/// one left by an entry with a wrong clock, are listed as a single empty
+/// bucket so they cannot blow up the report.
+pub const MAX_GAP: u64 = 60;
+
+/// Width of a bucket, in microseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interval(pub u64);
+
+impl FromStr for Interval {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let duration = match s {
+            "minute" => chrono::Duration::minutes(1),
+            "hour" => chrono::Duration::hours(1),
+            "day" => chrono::Duration::days(1),
+            _ => time::parse_duration(s).ok_or_else(|| {
+                format!(
+                    "invalid interval '{}', expected minute, hour, day or a duration such as 15min",
+                    s
+                )
+            })?,
+        };
+
+        match duration.num_microseconds() {
+            Some(usec) if usec > 0 => Ok(Interval(usec as u64)),
+            _ => Err(format!("invalid interval '{}', must be positive", s)),
+        }
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let units = [
+            (86_400 * USEC_PER_SEC, "d"),
+            (3_600 * USEC_PER_SEC, "h"),
+            (60 * USEC_PER_SEC, "min"),
+            (USEC_PER_SEC, "s"),
+            (1, "us"),
+        ];
+
+        let (size, unit) = units
+            .iter()
+            .find(|(size, _)| self.0.is_multiple_of(*size))
+            .unwrap();
+        write!(f, "{}{}", self.0 / size, unit)
+    }
+}
+
+/// Entry counts per interval, overall and per process.
+#[derive(Debug)]
+pub struct Histogram {
+    interval: Interval,
+    totals: BTreeMap<u64, u64>,
+    per_process: HashMap<String, BTreeMap<u64, u64>>,
+}
+
+impl Histogram {
+    pub fn new(interval: Interval) -> Self {
+        Self {
+            interval,
+            totals: BTreeMap::new(),
+            per_process: HashMap::new(),
+        }
+    }
+
+    pub fn interval(&self) -> Interval {
+        self.interval
+    }
+
+    /// Format the start of a bucket, to the minute unless the interval is
+    /// shorter.
+    pub fn format_start(&self, start: u64) -> String {
+        if self.interval.0.is_multiple_of(60 * USEC_PER_SEC) {
+            time::format_minute(start)
+        } else {
+            time::format_time(start)
+        }
+    }
+
+    /// Count an entry logged by `process` at `ts`.
+    pub fn add(&mut self, ts: u64, process: &str) {
+        let start = ts - ts % self.interval.0;
+        *self.totals.entry(start).or_insert(0) += 1;
+        *self
+            .per_process
+            .entry(process.to_string())
+            .or_default()
+            .entry(start)
+            .or_insert(0) += 1;
+    }
+
+    /// Start of every bucket from the first to the last one counted,
+    /// including the empty ones between them, gaps of more than [`MAX_GAP`]
+    /// empty buckets listed as their first bucket only.
+    pub fn starts(&self) -> Vec<u64> {
+        let interval = self.interval.0;
+        let mut starts = Vec::new();
+        let mut counted = self.totals.keys().copied().peekable();
+
+        while let Some(start) = counted.next() {
+            starts.push(start);
+            if let Some(next) = counted.peek() {
+                let empty = (next - start) / interval - 1;
+                let listed = if empty > MAX_GAP { 1 } else { empty };
+                starts.extend((1..=listed).map(|i| start + i * interval));
+            }
+        }
+        starts
+    }
+
+    fn series(&self, counts: &BTreeMap<u64, u64>) -> Vec<u64> {
+        self.starts()
+            .iter()
+            .map(|start| counts.get(start).copied().unwrap_or(0))
+            .collect()
+    }
+
+    /// Entries per bucket, in the order of `starts`.
+    pub fn counts(&self) -> Vec<u64> {
+        self.series(&self.totals)
+    }
+
+    /// The `n` processes logging the most, with their entries per bucket.
+    pub fn top_processes(&self, n: usize) -> Vec<(&str, Vec<u64>)> {
+        let mut totals: Vec<(&String, u64)> = self
+            .per_process
+            .iter()
+            .map(|(process, counts)| (process, counts.values().sum()))
+            .collect();
+        totals.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+
+        totals
+            .into_iter()
+            .take(n)
+            .map(|(process, _)| (process.as_str(), self.series(&self.per_process[process])))
+            .collect()
+    }
+}
+
+#[derive(Tabled)]
+#[allow(non_snake_case)]
+struct HistogramTableEntry {
+    Start: String,
+    Messages: u64,
+    Rate: String,
+}
+
+#[derive(Tabled)]
+#[allow(non_snake_case)]
+struct HistogramProcessTableEntry<'a> {
+    Process: &'a str,
+    Messages: u64,
+    Peak: u64,
+    Trend: String,
+}
+
+impl Analyzer for Histogram {
+    fn name(&self) -> &str {
+        "histogram"
+    }
+
+    /// Count an entry in the bucket of its timestamp, if it has one.
+    fn observe(&mut self, message: &Message, entry: &Entry) {
+        if let Some(ts) = source::timestamp(entry) {
+            self.add(ts, &message.process);
+        }
+    }
+
+    fn fork(&self) -> Box<dyn Analyzer> {
+        Box::new(Self::new(self.interval))
+    }
+
+    fn merge(&mut self, other: Box<dyn Analyzer>) -> crate::Result<()> {
+        let other = analyzer::downcast::<Self>(other)?;
+        for (start, count) in other.totals {
+            *self.totals.entry(start).or_insert(0) += count;
+        }
+        for (process, counts) in other.per_process {
+            let mine = self.per_process.entry(process).or_default();
+            for (start, count) in counts {
+                *mine.entry(start).or_insert(0) += count;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&self) -> Section {
+        Section {
+            name: self.name().to_string(),
+            title: format!("Messages per {}:", self.interval),
+            columns: vec!["Start".to_string(), "Messages".to_string()],
+            rows: self
+                .starts()
+                .into_iter()
+                .zip(self.counts())
+                .map(|(start, count)| vec![self.format_start(start).into(), count.into()])
+                .collect(),
+        }
+    }
+
+    fn report<'a>(&'a self, document: &mut Report<'a>) {
+        document.histogram = Some(report::HistogramReport {
+            interval_usec: self.interval.0,
+            starts: self.starts(),
+            counts: self.counts(),
+            per_process: self
+                .top_processes(PROCESSES)
+                .into_iter()
+                .map(|(process, counts)| report::ProcessSeries {
+                    process,
+                    messages: counts.iter().sum(),
+                    counts,
+                })
+                .collect(),
+        });
+    }
+
+    fn write_tables(&self, format: OutputFormat, out: &mut dyn Write) -> io::Result<()> {
+        let starts = self.starts();
+        let counts = self.counts();
+        let max = counts.iter().copied().max().unwrap_or(0);
+
+        if starts.is_empty() {
+            return Ok(());
+        }
+
+        let table = starts
+            .iter()
+            .zip(&counts)
+            .map(|(start, count)| HistogramTableEntry {
+                Start: self.format_start(*start),
+                Messages: *count,
+                Rate: bar(*count, max, WIDTH),
+            })
+            .collect();
+
+        writeln!(
+            out,
+            "{}",
+            report::table(format, &format!("Messages per {}:", self.interval), table)
+        )?;
+
+        let table = self
+            .top_processes(PROCESSES)
+            .into_iter()
+            .map(|(process, counts)| HistogramProcessTableEntry {
+                Process: process,
+                Messages: counts.iter().sum(),
+                Peak: counts.iter().copied().max().unwrap_or(0),
+                Trend: sparkline(&counts),
+            })
+            .collect();
+
+        writeln!(
+            out,
+            "{}",
+            report::table(
+                format,
+                &format!(
+                    "Busiest processes per {}, {} to {}:",
+                    self.interval,
+                    self.format_start(starts[0]),
+                    self.format_start(starts[starts.len() - 1])
+                ),
+                table
+            )
+        )
+    }
+}
+
+/// A bar of `width` characters at most, scaled to `max`.
+pub fn bar(count: u64, max: u64, width: usize) -> String {
+    if max == 0 {
+        return String::new();
+    }
+
+    let len = (count as f64 / max as f64 * width as f64).ceil() as usize;
+    "█".repeat(len)
+}
+
+/// One character per count, its height scaled to the largest count.
+pub fn sparkline(counts: &[u64]) -> String {
+    let max = counts.iter().copied().max().unwrap_or(0);
+
+    counts
+        .iter()
+        .map(|count| match count {
+            0 => ' ',
+            _ => SPARKS[((count * (SPARKS.len() as u64 - 1)) / max.max(1)) as usize],
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: u64 = 60 * USEC_PER_SEC;
+
+    #[test]
+    fn parses_and_formats_intervals() {
+        assert_eq!("hour".parse(), Ok(Interval(60 * MINUTE)));
+        assert_eq!("15min".parse(), Ok(Interval(15 * MINUTE)));
+        assert!("0s".parse::<Interval>().is_err());
+        assert!("fortnight".parse::<Interval>().is_err());
+
+        assert_eq!(Interval(15 * MINUTE).to_string(), "15min");
+        assert_eq!(Interval(24 * 60 * MINUTE).to_string(), "1d");
+        assert_eq!(Interval(90 * USEC_PER_SEC).to_string(), "90s");
+    }
+
+    #[test]
+    fn buckets_fill_gaps_and_rank_processes() {
+        let mut h = Histogram::new(Interval(MINUTE));
+        h.add(MINUTE + 1, "app");
+        h.add(MINUTE + 2, "app");
+        h.add(3 * MINUTE, "cron");
+        h.add(3 * MINUTE + 5, "app");
+
+        assert_eq!(h.starts(), vec![MINUTE, 2 * MINUTE, 3 * MINUTE]);
+        assert_eq!(h.counts(), vec![2, 0, 2]);
+        assert_eq!(h.top_processes(1), vec![("app", vec![2, 0, 1])]);
+        assert_eq!(sparkline(&[2, 0, 1]), "█ ▄");
+        assert_eq!(bar(1, 2, 10), "█████");
+    }
+
+    #[test]
+    fn long_gaps_collapse_to_one_bucket() {
+        let mut h = Histogram::new(Interval(MINUTE));
+        h.add(MINUTE, "app");
+        h.add(2 * MINUTE, "app");
+        // A clock decades off.
+        h.add(1_000_000_000 * MINUTE, "app");
+
+        assert_eq!(
+            h.starts(),
+            vec![MINUTE, 2 * MINUTE, 3 * MINUTE, 1_000_000_000 * MINUTE]
+        );
+        assert_eq!(h.counts(), vec![1, 1, 0, 1]);
+    }
+}
@@ -0,0 +1,276 @@
+//! Inventory of the fields entries carry, to audit which structured fields
+//! services actually write.
+//!
+//! Every field name seen is listed with the entries carrying it, the bytes
+//! its values take as counted by the [footprint](crate::footprint) and the
+//! processes writing it most. Address fields such as `__CURSOR` are not
+//! stored in entries and are left out.
+
+use crate::{
+    analyzer::{self, Analyzer, Section},
+    footprint::{self, Footprint},
+    report::{self, OutputFormat, Report},
+    source::Entry,
+    Message,
+};
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+};
+use tabled::Tabled;
+
+/// Number of processes listed for each field.
+const PROCESSES: usize = 3;
+
+/// Entries carrying one field.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FieldUse {
+    /// Entries carrying the field.
+    pub entries: u64,
+    /// Entries carrying the field per process.
+    pub per_process: HashMap<String, u64>,
+}
+
+/// The fields seen in the entries counted.
+#[derive(Debug, Default)]
+pub struct Inventory {
+    /// Entries counted, with any fields at all.
+    pub entries: u64,
+    pub fields: HashMap<String, FieldUse>,
+    // Bytes of the fields, and whether the rest of the footprint is reported
+    // as well.
+    footprint: Footprint,
+    report_footprint: bool,
+}
+
+impl Inventory {
+    /// An empty inventory, also reporting the footprint its bytes are taken
+    /// from if `report_footprint` is set.
+    pub fn new(report_footprint: bool) -> Self {
+        Self {
+            report_footprint,
+            ..Self::default()
+        }
+    }
+
+    /// Bytes of `field` stored as `FIELD=value`, over all entries.
+    pub fn bytes(&self, field: &str) -> u64 {
+        self.footprint.per_field.get(field).copied().unwrap_or(0)
+    }
+
+    /// Fields carried by the most entries first, ties broken by name.
+    pub fn ranked(&self) -> Vec<(&String, &FieldUse)> {
+        let mut ranked: Vec<_> = self.fields.iter().collect();
+        ranked.sort_by(|a, b| b.1.entries.cmp(&a.1.entries).then_with(|| a.0.cmp(b.0)));
+        ranked
+    }
+
+    fn percent(&self, field: &FieldUse) -> f64 {
+        field.entries as f64 / self.entries as f64 * 100.0
+    }
+}
+
+impl FieldUse {
+    /// The `n` processes writing the field in the most entries, ties broken
+    /// by name.
+    pub fn top_processes(&self, n: usize) -> Vec<(&String, u64)> {
+        let mut ranked = footprint::ranked(&self.per_process);
+        ranked.truncate(n);
+        ranked
+    }
+
+    /// The processes writing the field most, with their entries.
+    fn writers(&self) -> String {
+        self.top_processes(PROCESSES)
+            .into_iter()
+            .map(|(process, entries)| format!("{} ({})", process, entries))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+#[derive(Tabled)]
+#[allow(non_snake_case)]
+struct InventoryTableEntry<'a> {
+    Rank: usize,
+    Field: &'a str,
+    Entries: u64,
+    Percent: String,
+    Bytes: u64,
+    Processes: String,
+}
+
+impl Analyzer for Inventory {
+    fn name(&self) -> &str {
+        "inventory"
+    }
+
+    /// Count the fields of an entry, and their bytes.
+    fn observe(&mut self, message: &Message, entry: &Entry) {
+        self.footprint.observe(message, entry);
+        self.entries += 1;
+
+        for name in entry.keys().filter(|name| !name.starts_with("__")) {
+            let field = self.fields.entry(name.clone()).or_default();
+            field.entries += 1;
+            *field
+                .per_process
+                .entry(message.process.clone())
+                .or_insert(0) += 1;
+        }
+    }
+
+    fn fork(&self) -> Box<dyn Analyzer> {
+        Box::new(Self::new(self.report_footprint))
+    }
+
+    fn merge(&mut self, other: Box<dyn Analyzer>) -> crate::Result<()> {
+        let other = analyzer::downcast::<Self>(other)?;
+        self.footprint.merge(Box::new(other.footprint))?;
+        for (name, theirs) in other.fields {
+            let mine = self.fields.entry(name).or_default();
+            mine.entries += theirs.entries;
+            for (process, entries) in theirs.per_process {
+                *mine.per_process.entry(process).or_insert(0) += entries;
+            }
+        }
+        self.entries += other.entries;
+        Ok(())
+    }
+
+    fn finish(&self) -> Section {
+        Section {
+            name: self.name().to_string(),
+            title: format!("Fields seen in {} entries", self.entries),
+            columns: ["Rank", "Field", "Entries", "Percent", "Bytes", "Processes"]
+                .map(String::from)
+                .to_vec(),
+            rows: self
+                .ranked()
+                .into_iter()
+                .enumerate()
+                .map(|(i, (name, field))| {
+                    vec![
+                        (i + 1).into(),
+                        name.as_str().into(),
+                        field.entries.into(),
+                        ((self.percent(field) * 100.0).round() / 100.0).into(),
+                        self.bytes(name).into(),
+                        field.writers().into(),
+                    ]
+                })
+                .collect(),
+        }
+    }
+
+    fn report<'a>(&'a self, document: &mut Report<'a>) {
+        if self.report_footprint {
+            self.footprint.report(document);
+        }
+
+        document.inventory = Some(report::InventoryReport {
+            entries: self.entries,
+            fields: self
+                .ranked()
+                .into_iter()
+                .enumerate()
+                .map(|(i, (name, field))| report::FieldReport {
+                    rank: i + 1,
+                    field: name,
+                    entries: field.entries,
+                    percent: self.percent(field),
+                    bytes: self.bytes(name),
+                    processes: field
+                        .top_processes(PROCESSES)
+                        .into_iter()
+                        .map(|(process, entries)| report::FieldWriter { process, entries })
+                        .collect(),
+                })
+                .collect(),
+        });
+    }
+
+    fn write_tables(&self, format: OutputFormat, out: &mut dyn Write) -> io::Result<()> {
+        if self.report_footprint {
+            self.footprint.write_tables(format, out)?;
+        }
+
+        if self.entries == 0 {
+            return Ok(());
+        }
+
+        let table = self
+            .ranked()
+            .into_iter()
+            .enumerate()
+            .map(|(i, (name, field))| InventoryTableEntry {
+                Rank: i + 1,
+                Field: name,
+                Entries: field.entries,
+                Percent: format!("{:.02}", self.percent(field)),
+                Bytes: self.bytes(name),
+                Processes: field.writers(),
+            })
+            .collect();
+
+        writeln!(
+            out,
+            "{}",
+            report::table(
+                format,
+                &format!("Fields seen in {} entries", self.entries),
+                table
+            )
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(fields: &[(&str, &str)]) -> Entry {
+        fields
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn message(process: &str) -> Message {
+        Message {
+            msg: String::new(),
+            process: process.to_string(),
+            priority: "6".to_string(),
+        }
+    }
+
+    #[test]
+    fn lists_fields_and_their_writers() {
+        let mut inventory = Inventory::new(false);
+        let mut other = inventory.fork();
+        inventory.observe(
+            &message("app"),
+            &entry(&[("MESSAGE", "hi"), ("REQUEST_ID", "42")]),
+        );
+        inventory.observe(
+            &message("cron"),
+            &entry(&[("MESSAGE", "tick"), ("__CURSOR", "s=1")]),
+        );
+        other.observe(
+            &message("app"),
+            &entry(&[("MESSAGE", "bye"), ("REQUEST_ID", "43")]),
+        );
+        inventory.merge(other).unwrap();
+
+        assert_eq!(inventory.entries, 3);
+        let ranked = inventory.ranked();
+        let names: Vec<&str> = ranked.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["MESSAGE", "REQUEST_ID"]);
+
+        let message = ranked[0].1;
+        assert_eq!(message.entries, 3);
+        assert_eq!(inventory.bytes("MESSAGE"), 10 + 12 + 11);
+        assert_eq!(message.top_processes(1), [(&"app".to_string(), 2)]);
+        assert_eq!(ranked[1].1.per_process.len(), 1);
+    }
+}
@@ -0,0 +1,110 @@
+//! Reader for the line based JSON format written by `journalctl -o json`.
+//!
+//! Every line is a JSON object mapping field names to values. Values are
+//! strings, arrays of bytes for data that is not valid UTF-8, arrays of either
+//! for fields that occur more than once in an entry, or null for values
+//! journalctl chose not to output.
+
+use crate::source::{Entry, EntrySource};
+use serde_json::Value;
+use std::io::{self, BufRead};
+
+pub struct JsonReader<R: BufRead> {
+    reader: R,
+    line: String,
+    line_no: usize,
+}
+
+impl<R: BufRead> JsonReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            line: String::new(),
+            line_no: 0,
+        }
+    }
+}
+
+/// Turn a JSON field value into a string, `None` if it carries no data.
+fn field_value(value: Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s),
+        Value::Array(items) if items.iter().all(|v| v.is_u64()) => {
+            let bytes: Vec<u8> = items
+                .iter()
+                .filter_map(|v| v.as_u64())
+                .map(|b| b as u8)
+                .collect();
+            Some(String::from_utf8_lossy(&bytes).into())
+        }
+        // Repeated fields, keep the last like the other readers do.
+        Value::Array(items) => items.into_iter().filter_map(field_value).next_back(),
+        Value::Null => None,
+        other => Some(other.to_string()),
+    }
+}
+
+impl<R: BufRead> EntrySource for JsonReader<R> {
+    fn next_entry(&mut self) -> io::Result<Option<Entry>> {
+        loop {
+            self.line.clear();
+            if self.reader.read_line(&mut self.line)? == 0 {
+                return Ok(None);
+            }
+            self.line_no += 1;
+
+            if self.line.trim().is_empty() {
+                continue;
+            }
+
+            let object: serde_json::Map<String, Value> =
+                serde_json::from_str(&self.line).map_err(|e| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("line {}: {}", self.line_no, e),
+                    )
+                })?;
+
+            return Ok(Some(
+                object
+                    .into_iter()
+                    .filter_map(|(name, value)| field_value(value).map(|v| (name, v)))
+                    .collect(),
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_string_binary_and_repeated_fields() {
+        let input = concat!(
+            r#"{"MESSAGE":"hello","_COMM":"sshd","PRIORITY":"6"}"#,
+            "\n\n",
+            r#"{"MESSAGE":[104,105],"TAG":["a","b"],"BIG":null}"#,
+            "\n"
+        );
+        let mut reader = JsonReader::new(input.as_bytes());
+
+        let first = reader.next_entry().unwrap().unwrap();
+        assert_eq!(first["MESSAGE"], "hello");
+        assert_eq!(first["_COMM"], "sshd");
+
+        let second = reader.next_entry().unwrap().unwrap();
+        assert_eq!(second["MESSAGE"], "hi");
+        assert_eq!(second["TAG"], "b");
+        assert!(!second.contains_key("BIG"));
+
+        assert!(reader.next_entry().unwrap().is_none());
+    }
+
+    #[test]
+    fn invalid_line_is_an_error() {
+        let mut reader = JsonReader::new("{\"MESSAGE\":\n".as_bytes());
+        let err = reader.next_entry().unwrap_err();
+        assert!(err.to_string().starts_with("line 1:"));
+    }
+}
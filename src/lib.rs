@@ -0,0 +1,1326 @@
+//! Statistics on the contents of systemd journals: the most frequent and
+//! largest messages, per process shares, templates, byte footprints, field
+//! inventories and cardinalities, rates over time and bursts.
+//!
+//! Entries are read from an [`EntrySource`], opened with [`source::open`] or
+//! implemented for any other reader of journal entries. A [`JournalStat`] is
+//! configured with builder style setters, fed with [`JournalStat::parse`] and
+//! then either written out with [`JournalStat::report`] or inspected through
+//! its accessors and [`JournalStat::report_document`].
+//!
+//! ```no_run
+//! use journalstat::{source::{Backend, InputFormat}, JournalStat};
+//! use std::path::Path;
+//!
+//! # fn main() -> journalstat::Result<()> {
+//! let mut stat = JournalStat::open(
+//!     Path::new("/var/log/journal"),
+//!     InputFormat::Auto,
+//!     Backend::default(),
+//! )?;
+//! stat.n_frequent(10).parse()?;
+//!
+//! for (count, message) in stat.top_talkers() {
+//!     println!("{} {}: {}", count, message.process, message.msg);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! License: MIT
+
+use analyzer::{Analyzer, Section};
+use boot::Boots;
+use burst::{BurstConfig, BurstDetector};
+use cardinality::Cardinality;
+use diff::{Change, Diff};
+use exporter::Metrics;
+use fallback::Fallbacks;
+use filter::MatchExpr;
+use footprint::Footprint;
+use histogram::{Histogram, Interval};
+use inventory::Inventory;
+use messages::{Largest, Messages, Processes};
+use priority::PriorityRange;
+use regex::Regex;
+use report::OutputFormat;
+use source::{Backend, Entry, EntrySource, InputFormat};
+use std::{
+    any::Any,
+    collections::{HashMap, VecDeque},
+    hash::Hash,
+    io::{self, Write},
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+use tabled::{Table, Tabled};
+use template::{ClusterMode, TemplateMiner};
+
+pub use error::{Error, Result};
+
+/// Names of the built in statistics, in the order they are reported.
+const BUILTINS: &[&str] = &[
+    "per_process",
+    "footprint",
+    "inventory",
+    "cardinality",
+    "per_boot",
+    "histogram",
+    "bursts",
+    "top_talkers",
+    "templates",
+    "largest",
+];
+
+pub mod analyzer;
+pub mod boot;
+pub mod burst;
+pub mod cardinality;
+pub mod diff;
+mod error;
+mod export;
+pub mod exporter;
+pub mod fallback;
+pub mod filter;
+pub mod footprint;
+pub mod heavy;
+pub mod histogram;
+pub mod inventory;
+mod json;
+pub mod messages;
+#[cfg(feature = "native")]
+mod native;
+mod parallel;
+pub mod priority;
+pub mod report;
+pub mod rules;
+pub mod source;
+pub mod template;
+pub mod time;
+#[cfg(feature = "tui")]
+pub mod tui;
+
+/// A distinct message, counted separately for every process and priority
+/// it was logged with.
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Clone)]
+pub struct Message {
+    /// Message contents.
+    pub msg: String,
+    /// The process that generated the message.
+    pub process: String,
+    /// Priority the message was sent at.
+    pub priority: String,
+}
+
+/// How [`JournalStat::follow`] keeps a report up to date.
+pub struct Refresh<'a> {
+    /// Time between reports.
+    pub every: Duration,
+    /// Where the reports are written.
+    pub out: &'a mut dyn Write,
+    /// Clear the terminal before each report rather than appending it.
+    pub redraw: bool,
+}
+
+/// Called with problems met that did not stop reading.
+pub type OnWarning = Box<dyn FnMut(&str)>;
+
+/// Statistics gathered from the entries of a journal.
+pub struct JournalStat {
+    // Input file/directory for debug purposes.
+    input: PathBuf,
+    // Filtering on a systemd unit.
+    unit: Option<String>,
+    // Handle to the journal.
+    journal: Box<dyn EntrySource>,
+    // Number of top talkers to report on, and the most distinct messages
+    // tracked when counting approximately.
+    n_top_talkers: usize,
+    counters: Option<NonZeroUsize>,
+    // How messages are grouped into templates, if they are, and the number
+    // of templates to report on.
+    cluster: Option<ClusterMode>,
+    n_templates: usize,
+    // Whether the bytes logged and the fields seen are reported.
+    footprint: bool,
+    inventory: bool,
+    // Total number of messages parsed.
+    total_msgs: u64,
+    // Entries counted with a fallback for a missing field, or skipped.
+    fallbacks: Fallbacks,
+    // Regex to match on.
+    regex: Option<Regex>,
+    // Filtering on field matches.
+    matches: Option<MatchExpr>,
+    // Filtering on a range of priorities.
+    priority: Option<PriorityRange>,
+    // Filtering on a boot ID.
+    boot: Option<String>,
+    // Statistics gathered, the first `builtins` of them built in and kept in
+    // the order of BUILTINS, then those plugged in from outside.
+    analyzers: Vec<Box<dyn Analyzer>>,
+    builtins: usize,
+    // Time window to report on, in microseconds since the epoch.
+    since: Option<u64>,
+    until: Option<u64>,
+    // Timestamps of the first and last entries within the window.
+    first_seen: Option<u64>,
+    last_seen: Option<u64>,
+    // How to write the report.
+    format: OutputFormat,
+    // Exported counters, kept instead of the statistics when exporting.
+    metrics: Option<Metrics>,
+    // Length of the sliding window in microseconds, and the entries in it.
+    window: Option<u64>,
+    recent: VecDeque<(u64, Entry)>,
+    // Told about problems that did not stop reading.
+    on_warning: Option<OnWarning>,
+}
+
+#[derive(Tabled)]
+#[allow(non_snake_case)]
+struct FallbackTableEntry {
+    Reason: &'static str,
+    Entries: u64,
+}
+
+#[derive(Tabled)]
+#[allow(non_snake_case)]
+struct ProcessChangeTableEntry<'a> {
+    Process: &'a str,
+    Before: u32,
+    After: u32,
+    Change: String,
+    Relative: String,
+}
+
+#[derive(Tabled)]
+#[allow(non_snake_case)]
+struct MessageChangeTableEntry<'a> {
+    Process: &'a str,
+    Priority: String,
+    Before: u32,
+    After: u32,
+    Change: String,
+    Relative: String,
+    Message: &'a str,
+}
+
+#[derive(Tabled)]
+#[allow(non_snake_case)]
+struct BootTableEntry<'a> {
+    Offset: i64,
+    BootId: &'a str,
+    First: String,
+    Last: String,
+    Messages: u64,
+}
+
+impl JournalStat {
+    /// Create a new JournalStat struct reading entries from `journal`.
+    pub fn new(path: &Path, journal: Box<dyn EntrySource>) -> Self {
+        let mut stat = Self {
+            input: path.to_path_buf(),
+            journal,
+            unit: None,
+            n_top_talkers: 0,
+            counters: None,
+            cluster: None,
+            n_templates: 10,
+            footprint: false,
+            inventory: false,
+            analyzers: Vec::new(),
+            builtins: 0,
+            total_msgs: 0,
+            fallbacks: Fallbacks::default(),
+            regex: None,
+            matches: None,
+            priority: None,
+            boot: None,
+            since: None,
+            until: None,
+            first_seen: None,
+            last_seen: None,
+            format: OutputFormat::default(),
+            metrics: None,
+            window: None,
+            recent: VecDeque::new(),
+            on_warning: None,
+        };
+        stat.set_builtin("per_process", Some(Box::<Processes>::default()));
+        stat.set_messages();
+        stat.n_largest(10);
+        stat
+    }
+
+    /// Set the regex to filter on.
+    pub fn set_regex(&mut self, regex: &Option<Regex>) -> &mut Self {
+        self.regex = regex.clone();
+        self
+    }
+
+    /// Filter on a particular systemd unit.
+    pub fn set_filter_unit(&mut self, unit: &Option<String>) -> &mut Self {
+        self.unit = unit.clone();
+        self
+    }
+
+    /// Filter on a field match expression.
+    pub fn set_filter_matches(&mut self, matches: Option<MatchExpr>) -> &mut Self {
+        self.matches = matches;
+        self
+    }
+
+    /// Filter on a range of priorities.
+    pub fn set_filter_priority(&mut self, priority: Option<PriorityRange>) -> &mut Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Filter on a particular boot ID.
+    pub fn set_filter_boot(&mut self, boot: &Option<String>) -> &mut Self {
+        self.boot = boot.clone();
+        self
+    }
+
+    /// Record per process statistics for each boot as well as overall.
+    pub fn per_boot(&mut self, per_boot: bool) -> &mut Self {
+        self.set_builtin("per_boot", per_boot.then(|| Box::<Boots>::default() as _));
+        self
+    }
+
+    /// Gather another statistic, reported after the built in ones.
+    pub fn analyzer(&mut self, analyzer: Box<dyn Analyzer>) -> &mut Self {
+        self.analyzers.push(analyzer);
+        self
+    }
+
+    /// Call `on_warning` with every problem met that did not stop reading,
+    /// such as a journal file skipped because it could not be read. They are
+    /// ignored otherwise.
+    pub fn on_warning(&mut self, on_warning: OnWarning) -> &mut Self {
+        self.on_warning = Some(on_warning);
+        self
+    }
+
+    /// Count messages in intervals of their timestamp.
+    pub fn histogram(&mut self, interval: Option<Interval>) -> &mut Self {
+        self.set_builtin(
+            "histogram",
+            interval.map(|interval| Box::new(Histogram::new(interval)) as _),
+        );
+        self
+    }
+
+    /// Look for bursts of messages, if the configuration asks for any.
+    pub fn detect_bursts(&mut self, config: BurstConfig) -> &mut Self {
+        self.set_builtin(
+            "bursts",
+            config
+                .enabled()
+                .then(|| Box::new(BurstDetector::new(config)) as _),
+        );
+        self
+    }
+
+    /// Attribute the bytes logged to processes, units and fields.
+    pub fn footprint(&mut self, footprint: bool) -> &mut Self {
+        self.footprint = footprint;
+        self.set_footprint();
+        self
+    }
+
+    /// List every field seen with the entries and bytes it takes and the
+    /// processes writing it.
+    pub fn inventory(&mut self, inventory: bool) -> &mut Self {
+        self.inventory = inventory;
+        self.set_footprint();
+        self
+    }
+
+    /// Gather the footprint and the inventory asked for, counting the bytes
+    /// once: the inventory takes its bytes from a footprint of its own, which
+    /// it reports as well if the footprint was asked for.
+    fn set_footprint(&mut self) {
+        let (footprint, inventory) = (self.footprint, self.inventory);
+        self.set_builtin(
+            "footprint",
+            (footprint && !inventory).then(|| Box::<Footprint>::default() as _),
+        );
+        self.set_builtin(
+            "inventory",
+            inventory.then(|| Box::new(Inventory::new(footprint)) as _),
+        );
+    }
+
+    /// Count the distinct values of `fields`, overall and per process.
+    pub fn cardinality(&mut self, fields: Option<Vec<String>>) -> &mut Self {
+        self.set_builtin(
+            "cardinality",
+            fields.map(|fields| Box::new(Cardinality::new(fields)) as _),
+        );
+        self
+    }
+
+    /// Gather the built in statistic `name` with `analyzer`, in place of the
+    /// one gathered so far, or stop gathering it for None.
+    fn set_builtin(&mut self, name: &str, analyzer: Option<Box<dyn Analyzer>>) {
+        let rank = |name: &str| BUILTINS.iter().position(|b| *b == name);
+
+        if let Some(i) = self.analyzers[..self.builtins]
+            .iter()
+            .position(|a| a.name() == name)
+        {
+            self.analyzers.remove(i);
+            self.builtins -= 1;
+        }
+
+        if let Some(analyzer) = analyzer {
+            let i = self.analyzers[..self.builtins]
+                .iter()
+                .position(|a| rank(a.name()) > rank(name))
+                .unwrap_or(self.builtins);
+            self.analyzers.insert(i, analyzer);
+            self.builtins += 1;
+        }
+    }
+
+    /// Restrict parsing to entries between `since` and `until`, inclusive.
+    pub fn set_time_range(&mut self, since: Option<u64>, until: Option<u64>) -> &mut Self {
+        self.since = since;
+        self.until = until;
+        self
+    }
+
+    /// Only report on the entries within `window` of the present while
+    /// following.
+    pub fn sliding_window(&mut self, window: Option<Interval>) -> &mut Self {
+        self.window = window.map(|w| w.0);
+        self
+    }
+
+    /// Set the format the report is written in.
+    pub fn output_format(&mut self, format: OutputFormat) -> &mut Self {
+        self.format = format;
+        self
+    }
+
+    /// Open the journal file or directory at `path`, or stdin for "-", and
+    /// create a JournalStat reading it.
+    pub fn open(path: &Path, format: InputFormat, backend: Backend) -> Result<Self> {
+        let journal = source::open(path, format, backend).map_err(|source| Error::Open {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Ok(Self::new(path, journal))
+    }
+
+    /// Count entries into exported metrics instead of gathering statistics
+    /// for a report.
+    pub fn export(&mut self, metrics: Option<Metrics>) -> &mut Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Set the number of top talkers to watch for.
+    pub fn n_frequent(&mut self, n_freq: usize) -> &mut Self {
+        self.n_top_talkers = n_freq;
+        self.set_messages();
+        self
+    }
+
+    /// Count messages approximately, tracking at most `counters` distinct
+    /// messages rather than every one seen.
+    pub fn approximate(&mut self, counters: Option<NonZeroUsize>) -> &mut Self {
+        self.counters = counters;
+        self.set_messages();
+        self
+    }
+
+    /// Count messages as asked for. Messages are always counted.
+    fn set_messages(&mut self) {
+        let messages = Messages::new(self.n_top_talkers, self.counters);
+        self.set_builtin("top_talkers", Some(Box::new(messages)));
+    }
+
+    /// Group messages into templates with the given mode.
+    pub fn cluster(&mut self, mode: Option<ClusterMode>) -> &mut Self {
+        self.cluster = mode;
+        self.set_templates();
+        self
+    }
+
+    /// Set the number of templates to report on.
+    pub fn n_templates(&mut self, n_templates: usize) -> &mut Self {
+        self.n_templates = n_templates;
+        self.set_templates();
+        self
+    }
+
+    /// Group messages into templates as asked for, if they are.
+    fn set_templates(&mut self) {
+        let n_templates = self.n_templates;
+        self.set_builtin(
+            "templates",
+            self.cluster
+                .map(|mode| Box::new(TemplateMiner::new(mode, n_templates)) as _),
+        );
+    }
+
+    /// Set the top number of large messages to record.
+    pub fn n_largest(&mut self, n_largest: usize) -> &mut Self {
+        self.set_builtin("largest", Some(Box::new(Largest::new(n_largest))));
+        self
+    }
+
+    /// Read the journal and record any statistics.
+    pub fn parse(&mut self) -> Result<&mut Self> {
+        self.start();
+
+        while let Some(entry) = self.journal.next_entry()? {
+            if !self.take(&entry) {
+                break;
+            }
+        }
+
+        self.pass_on_warnings();
+        self.complete();
+        Ok(self)
+    }
+
+    /// Read the journal and keep reading entries as they are written, until
+    /// one is past the end of the time range. With `refresh`, the report is
+    /// written periodically once the entries already written are read.
+    pub fn follow(&mut self, poll: Duration, mut refresh: Option<Refresh>) -> Result<()> {
+        self.start();
+        let mut refreshed: Option<Instant> = None;
+
+        loop {
+            let entry = self.journal.next_entry()?;
+            if let Some(entry) = &entry {
+                if !self.take(entry) {
+                    break;
+                }
+            }
+
+            if let Some(refresh) = &mut refresh {
+                let due = match refreshed {
+                    Some(at) => at.elapsed() >= refresh.every,
+                    None => entry.is_none(),
+                };
+                if due {
+                    self.report_live(time::now(), refresh)?;
+                    refreshed = Some(Instant::now());
+                }
+            }
+
+            if entry.is_none() {
+                self.pass_on_warnings();
+                self.journal.wait(poll)?;
+            }
+        }
+
+        self.pass_on_warnings();
+        self.complete();
+        Ok(())
+    }
+
+    /// Report a problem that did not stop reading.
+    fn warn(&mut self, warning: &str) {
+        if let Some(on_warning) = &mut self.on_warning {
+            on_warning(warning);
+        }
+    }
+
+    /// Report the problems the journal met since last asked.
+    fn pass_on_warnings(&mut self) {
+        for warning in self.journal.take_warnings() {
+            self.warn(&warning);
+        }
+    }
+
+    /// Set up the journal before reading from it.
+    fn start(&mut self) {
+        if let Some(groups) = self.matches.as_ref().and_then(|m| m.equality_groups()) {
+            // Narrows what the source returns, the full expression is still
+            // evaluated for every entry.
+            if let Err(e) = self.journal.add_matches(&groups) {
+                self.warn(&format!("filtering in the journal failed: {}", e));
+            }
+        }
+
+        if let Some(since) = self.since {
+            // Entries before the window are skipped below if seeking fails.
+            let _ = self.journal.seek_realtime(since);
+        }
+    }
+
+    /// Record an entry if it is within the time range. Returns false once
+    /// past the end of the range.
+    fn take(&mut self, entry: &Entry) -> bool {
+        let ts = source::timestamp(entry);
+
+        if self.since.is_some() || self.until.is_some() {
+            match ts {
+                Some(ts) if self.since.is_some_and(|since| ts < since) => return true,
+                // Entries are in time order, nothing later can match.
+                Some(ts) if self.until.is_some_and(|until| ts > until) => return false,
+                Some(_) => {}
+                None => return true,
+            }
+        }
+
+        if let (Some(window), Some(ts)) = (self.window, ts) {
+            // Entries are in time order, older ones have left the window.
+            while self
+                .recent
+                .front()
+                .is_some_and(|(old, _)| old + window < ts)
+            {
+                self.recent.pop_front();
+            }
+            self.recent.push_back((ts, entry.clone()));
+        }
+
+        self.count(entry, ts);
+        true
+    }
+
+    /// Count an entry within the time range.
+    fn count(&mut self, entry: &Entry, ts: Option<u64>) {
+        if let Some(ts) = ts {
+            self.first_seen = Some(self.first_seen.map_or(ts, |first| first.min(ts)));
+            self.last_seen = Some(self.last_seen.map_or(ts, |last| last.max(ts)));
+        }
+
+        self.record(entry);
+    }
+
+    /// Recount the statistics from the entries of the sliding window ending
+    /// at `now`, if there is one.
+    fn slide(&mut self, now: u64) {
+        let window = match self.window {
+            Some(window) => window,
+            None => return,
+        };
+
+        while self.recent.front().is_some_and(|(ts, _)| ts + window < now) {
+            self.recent.pop_front();
+        }
+
+        self.reset();
+        let recent = std::mem::take(&mut self.recent);
+        for (ts, entry) in &recent {
+            self.count(entry, Some(*ts));
+        }
+        self.recent = recent;
+    }
+
+    /// Drop the statistics gathered so far, keeping the settings.
+    fn reset(&mut self) {
+        self.total_msgs = 0;
+        self.fallbacks = Fallbacks::default();
+        self.analyzers = self.analyzers.iter().map(|a| a.fork()).collect();
+        self.first_seen = None;
+        self.last_seen = None;
+    }
+
+    /// Write the report on the entries read so far while following, in
+    /// place of the previous one when redrawing.
+    fn report_live(&mut self, now: u64, refresh: &mut Refresh) -> Result<()> {
+        self.slide(now);
+        self.complete();
+
+        if refresh.redraw {
+            write!(refresh.out, "\x1b[2J\x1b[H")?;
+        }
+        self.report(refresh.out)
+    }
+
+    /// Bring the statistics up to date with the entries read so far.
+    fn complete(&mut self) {
+        for analyzer in &mut self.analyzers {
+            analyzer.complete();
+        }
+    }
+
+    /// Record the statistics for a single journal entry.
+    fn record(&mut self, entry: &Entry) {
+        let priority = entry.get("PRIORITY");
+        let has_priority = priority.is_some();
+        let priority = priority.map_or(fallback::DEFAULT_PRIORITY, |p| p.as_str());
+
+        if let Some(unit) = &self.unit {
+            if let Some(junit) = entry.get("_SYSTEMD_UNIT") {
+                if !unit.eq(junit) {
+                    return;
+                }
+            }
+        }
+
+        if let Some(matches) = &self.matches {
+            if !matches.matches(entry) {
+                return;
+            }
+        }
+
+        if let Some(range) = &self.priority {
+            if !range.contains(priority) {
+                return;
+            }
+        }
+
+        if let Some(boot) = &self.boot {
+            if entry.get(boot::BOOT_ID) != Some(boot) {
+                return;
+            }
+        }
+
+        // An entry without a message cannot match the pattern, so it is
+        // left out rather than skipped.
+        if let Some(regex) = &self.regex {
+            if !entry.get("MESSAGE").is_some_and(|msg| regex.is_match(msg)) {
+                return;
+            }
+        }
+
+        // Only entries left by the filters are counted as skipped.
+        let (msg, (process_name, process_field)) =
+            match (entry.get("MESSAGE"), fallback::process(entry)) {
+                (Some(msg), Some(process)) => (msg, process),
+                (None, _) => {
+                    self.fallbacks.skipped_no_message += 1;
+                    return;
+                }
+                (_, None) => {
+                    self.fallbacks.skipped_no_process += 1;
+                    return;
+                }
+            };
+
+        if let Some(metrics) = &self.metrics {
+            metrics.observe(process_name, priority, msg, entry);
+            return;
+        }
+
+        self.total_msgs += 1;
+        self.fallbacks.observe(process_field, has_priority);
+
+        let key = Message {
+            msg: msg.clone(),
+            process: process_name.to_string(),
+            priority: priority.to_string(),
+        };
+
+        for analyzer in &mut self.analyzers {
+            analyzer.observe(&key, entry);
+        }
+    }
+
+    /// Number of messages counted.
+    pub fn total_messages(&self) -> u64 {
+        self.total_msgs
+    }
+
+    /// Messages counted per process.
+    pub fn per_process(&self) -> &HashMap<String, u32> {
+        self.builtin::<Processes>()
+            .expect("processes are always counted")
+            .counts()
+    }
+
+    /// Entries counted with a fallback for a missing field, or skipped.
+    pub fn fallbacks(&self) -> &Fallbacks {
+        &self.fallbacks
+    }
+
+    /// Times every distinct message was counted.
+    pub fn message_counts(&self) -> &HashMap<Message, u32> {
+        self.messages().counts()
+    }
+
+    /// The most frequent messages and their counts, most frequent first.
+    pub fn top_talkers(&self) -> &[(u32, Message)] {
+        self.messages().top_talkers()
+    }
+
+    /// The largest messages recorded.
+    pub fn largest(&self) -> &[String] {
+        self.builtin::<Largest>()
+            .expect("the largest messages are always kept")
+            .messages()
+    }
+
+    /// The message counts, always gathered.
+    pub(crate) fn messages(&self) -> &Messages {
+        self.builtin().expect("messages are always counted")
+    }
+
+    /// The built in statistic of type `A`, if it is gathered.
+    fn builtin<A: Analyzer>(&self) -> Option<&A> {
+        self.analyzers[..self.builtins]
+            .iter()
+            .find_map(|a| (a.as_ref() as &dyn Any).downcast_ref())
+    }
+
+    /// The sections of the analyzers added, in the order they were added.
+    pub fn sections(&self) -> Vec<Section> {
+        self.analyzers[self.builtins..]
+            .iter()
+            .map(|a| a.finish())
+            .collect()
+    }
+
+    /// Write the report in the selected output format.
+    pub fn report(&self, out: &mut dyn Write) -> Result<()> {
+        match self.format {
+            OutputFormat::Json => self.report_json(out),
+            _ => self.report_tables(out),
+        }
+    }
+
+    /// Gather every report section into one document.
+    pub fn report_document(&self) -> report::Report<'_> {
+        let input = self.input.to_string_lossy();
+
+        let mut document = report::Report {
+            schema_version: report::SCHEMA_VERSION,
+            input,
+            filters: report::Filters {
+                unit: self.unit.as_deref(),
+                pattern: self.regex.as_ref().map(|r| r.as_str()),
+                matches: self.matches.as_ref().map_or(&[], |m| m.terms()),
+                priority: self.priority.map(|p| report::PriorityFilter {
+                    min: p.min,
+                    max: p.max,
+                }),
+                boot: self.boot.as_deref(),
+            },
+            time_range: report::TimeRange {
+                since: self.since,
+                until: self.until,
+                first_entry: self.first_seen,
+                last_entry: self.last_seen,
+            },
+            totals: report::Totals {
+                messages: self.total_msgs,
+                processes: 0,
+                distinct_messages: None,
+            },
+            fallbacks: &self.fallbacks,
+            per_process: Vec::new(),
+            footprint: None,
+            inventory: None,
+            cardinality: None,
+            histogram: None,
+            bursts: None,
+            top_talkers: Vec::new(),
+            largest: Vec::new(),
+            templates: None,
+            per_boot: None,
+            sections: Vec::new(),
+            approximate: None,
+        };
+
+        for analyzer in &self.analyzers {
+            analyzer.report(&mut document);
+        }
+        document
+    }
+
+    /// Write the report as a single JSON document.
+    fn report_json(&self, out: &mut dyn Write) -> Result<()> {
+        serde_json::to_writer_pretty(&mut *out, &self.report_document())?;
+        writeln!(out)?;
+        Ok(())
+    }
+
+    /// Write a titled table in the selected format.
+    fn print_table<T: Tabled>(
+        &self,
+        out: &mut dyn Write,
+        title: &str,
+        rows: Vec<T>,
+    ) -> io::Result<()> {
+        writeln!(out, "{}", report::table(self.format, title, rows))
+    }
+
+    /// Write the report as tables.
+    fn report_tables(&self, out: &mut dyn Write) -> Result<()> {
+        // CSV is tables only, the time range is in the JSON report.
+        if self.format != OutputFormat::Csv {
+            let (heading, line_break) = match self.format {
+                OutputFormat::Markdown => ("# ", "  "),
+                _ => ("", ""),
+            };
+
+            writeln!(
+                out,
+                "{}Journal statistics for {}",
+                heading,
+                self.input.display()
+            )?;
+            if self.format == OutputFormat::Markdown {
+                writeln!(out)?;
+            }
+            writeln!(
+                out,
+                "Time range: {} to {}{}",
+                self.since
+                    .map_or("start of journal".to_string(), time::format_time),
+                self.until
+                    .map_or("end of journal".to_string(), time::format_time),
+                line_break
+            )?;
+            if let (Some(first), Some(last)) = (self.first_seen, self.last_seen) {
+                writeln!(
+                    out,
+                    "Entries seen: {} to {}{}",
+                    time::format_time(first),
+                    time::format_time(last),
+                    line_break
+                )?;
+            }
+            if let Some(approximation) = self.messages().approximation() {
+                writeln!(
+                    out,
+                    "Approximate counts of {} distinct messages at most, each at most {} over",
+                    approximation.counters, approximation.max_error
+                )?;
+            }
+            if self.format == OutputFormat::Markdown {
+                writeln!(out)?;
+            }
+        }
+
+        let fallbacks: Vec<FallbackTableEntry> = self
+            .fallbacks
+            .reasons()
+            .into_iter()
+            .map(|(reason, entries)| FallbackTableEntry {
+                Reason: reason,
+                Entries: entries,
+            })
+            .collect();
+        if !fallbacks.is_empty() {
+            self.print_table(out, "Entries with missing fields", fallbacks)?;
+        }
+
+        for analyzer in &self.analyzers {
+            analyzer.write_tables(self.format, out)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Write the boots in the journal, numbered like `journalctl --list-boots`.
+pub fn report_boots(out: &mut dyn Write, boots: &[boot::Boot]) -> Result<()> {
+    let mut table = Vec::new();
+
+    for (i, boot) in boots.iter().enumerate() {
+        table.push(BootTableEntry {
+            Offset: i as i64 + 1 - boots.len() as i64,
+            BootId: &boot.id,
+            First: boot.first.map_or("-".to_string(), time::format_time),
+            Last: boot.last.map_or("-".to_string(), time::format_time),
+            Messages: boot.count,
+        });
+    }
+
+    writeln!(out, "{}", Table::new(table))?;
+    Ok(())
+}
+
+/// Describe a change relative to the count before.
+fn relative<K>(change: &Change<K>) -> String {
+    match change.ratio() {
+        Some(ratio) => format!("x{:.2}", ratio),
+        None => "new".to_string(),
+    }
+}
+
+/// The input and time window statistics were gathered for.
+fn describe(stat: &JournalStat) -> String {
+    format!(
+        "{}, {} to {}",
+        stat.input.display(),
+        stat.since
+            .map_or("start of journal".to_string(), time::format_time),
+        stat.until
+            .map_or("end of journal".to_string(), time::format_time)
+    )
+}
+
+fn diff_side(stat: &JournalStat) -> report::DiffSide<'_> {
+    report::DiffSide {
+        input: stat.input.to_string_lossy(),
+        since: stat.since,
+        until: stat.until,
+        messages: stat.total_msgs,
+    }
+}
+
+fn message_changes<'a>(changes: &[Change<&'a Message>]) -> Vec<report::MessageChange<'a>> {
+    changes
+        .iter()
+        .map(|c| report::MessageChange {
+            process: &c.key.process,
+            priority: &c.key.priority,
+            message: &c.key.msg,
+            before: c.before,
+            after: c.after,
+            delta: c.delta(),
+            ratio: c.ratio(),
+        })
+        .collect()
+}
+
+/// Write what changed from `before` to `after`, listing `n` messages per
+/// section.
+pub fn report_diff(
+    out: &mut dyn Write,
+    before: &JournalStat,
+    after: &JournalStat,
+    n: usize,
+    format: OutputFormat,
+) -> Result<()> {
+    if before.messages().is_approximate() || after.messages().is_approximate() {
+        return Err(Error::Invalid(
+            "diff needs exact message counts, it cannot be used with --approximate".to_string(),
+        ));
+    }
+
+    let diff = Diff::new(
+        (before.message_counts(), before.per_process()),
+        (after.message_counts(), after.per_process()),
+        n,
+    );
+
+    if format == OutputFormat::Json {
+        let document = report::DiffReport {
+            schema_version: report::SCHEMA_VERSION,
+            before: diff_side(before),
+            after: diff_side(after),
+            processes: diff
+                .processes
+                .iter()
+                .map(|c| report::ProcessChange {
+                    process: c.key,
+                    before: c.before,
+                    after: c.after,
+                    delta: c.delta(),
+                    ratio: c.ratio(),
+                })
+                .collect(),
+            new_messages: message_changes(&diff.new),
+            vanished_messages: message_changes(&diff.vanished),
+            largest_absolute: message_changes(&diff.absolute),
+            largest_relative: message_changes(&diff.relative),
+        };
+        serde_json::to_writer_pretty(&mut *out, &document)?;
+        writeln!(out)?;
+        return Ok(());
+    }
+
+    if format != OutputFormat::Csv {
+        let heading = if format == OutputFormat::Markdown {
+            "# "
+        } else {
+            ""
+        };
+        writeln!(out, "{}Before: {}", heading, describe(before))?;
+        writeln!(out, "{}After: {}", heading, describe(after))?;
+        if format == OutputFormat::Markdown {
+            writeln!(out)?;
+        }
+    }
+
+    let mut table = Vec::new();
+    for change in &diff.processes {
+        table.push(ProcessChangeTableEntry {
+            Process: change.key,
+            Before: change.before,
+            After: change.after,
+            Change: format!("{:+}", change.delta()),
+            Relative: relative(change),
+        });
+    }
+    writeln!(
+        out,
+        "{}",
+        report::table(format, "Per process changes:", table)
+    )?;
+
+    for (title, changes) in [
+        ("New messages:", &diff.new),
+        ("Vanished messages:", &diff.vanished),
+        ("Largest absolute changes:", &diff.absolute),
+        ("Largest relative changes:", &diff.relative),
+    ] {
+        let mut table = Vec::new();
+        for change in changes {
+            table.push(MessageChangeTableEntry {
+                Process: &change.key.process,
+                Priority: priority::name(&change.key.priority)
+                    .unwrap_or(&change.key.priority)
+                    .to_string(),
+                Before: change.before,
+                After: change.after,
+                Change: format!("{:+}", change.delta()),
+                Relative: relative(change),
+                Message: &change.key.msg,
+            });
+        }
+        writeln!(out, "{}", report::table(format, title, table))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{cell::RefCell, rc::Rc};
+
+    /// Build a JournalStat reading the given entries.
+    fn stat(entries: Vec<Entry>) -> JournalStat {
+        JournalStat::new(Path::new("test"), Box::new(entries.into_iter()))
+    }
+
+    fn entry(msg: &str, process: &str, priority: &str) -> Entry {
+        let mut entry = Entry::new();
+        entry.insert("MESSAGE".to_string(), msg.to_string());
+        entry.insert("_COMM".to_string(), process.to_string());
+        entry.insert("PRIORITY".to_string(), priority.to_string());
+        entry
+    }
+
+    fn ranking(stat: &JournalStat) -> Vec<(u32, &str, &str)> {
+        stat.top_talkers()
+            .iter()
+            .map(|(count, msg)| (*count, msg.process.as_str(), msg.msg.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn top_talkers_ranked_by_frequency() {
+        // Interleave so that early, infrequent messages would previously
+        // have claimed the first slots.
+        let feed = [
+            ("a", "sshd"),
+            ("b", "cron"),
+            ("c", "kernel"),
+            ("b", "cron"),
+            ("c", "kernel"),
+            ("c", "kernel"),
+            ("d", "dbus"),
+            ("c", "kernel"),
+            ("b", "cron"),
+        ];
+        let mut stat = stat(feed.iter().map(|(m, p)| entry(m, p, "6")).collect());
+        stat.n_frequent(3).parse().unwrap();
+
+        assert_eq!(
+            ranking(&stat),
+            vec![(4, "kernel", "c"), (3, "cron", "b"), (1, "sshd", "a")]
+        );
+    }
+
+    #[test]
+    fn top_talkers_unique_and_tie_broken() {
+        let mut entries = vec![entry("same", "app", "6"); 5];
+        entries.push(entry("zeta", "app", "6"));
+        entries.push(entry("alpha", "app", "6"));
+        entries.push(entry("same", "other", "6"));
+        let mut stat = stat(entries);
+        stat.n_frequent(10).parse().unwrap();
+
+        // Each message appears once, ties ordered by message then process.
+        assert_eq!(
+            ranking(&stat),
+            vec![
+                (5, "app", "same"),
+                (1, "app", "alpha"),
+                (1, "other", "same"),
+                (1, "app", "zeta"),
+            ]
+        );
+    }
+
+    #[test]
+    fn top_talkers_disabled_by_default() {
+        let mut stat = stat(vec![entry("a", "app", "6")]);
+        stat.parse().unwrap();
+
+        assert!(stat.top_talkers().is_empty());
+    }
+
+    #[test]
+    fn time_range_limits_entries() {
+        let entries = (1..=5)
+            .map(|ts| {
+                let mut e = entry(&format!("m{}", ts), "app", "6");
+                e.insert(source::REALTIME_TIMESTAMP.to_string(), ts.to_string());
+                e
+            })
+            .collect();
+        let mut stat = stat(entries);
+        stat.set_time_range(Some(2), Some(4)).parse().unwrap();
+
+        assert_eq!(stat.total_msgs, 3);
+        assert_eq!((stat.first_seen, stat.last_seen), (Some(2), Some(4)));
+    }
+
+    #[test]
+    fn sliding_window_recounts_recent_entries() {
+        let mut stat = stat(Vec::new());
+        stat.sliding_window(Some(Interval(2)))
+            .histogram(Some(Interval(1)))
+            .n_frequent(1);
+        for ts in 1..=5 {
+            let mut e = entry(if ts < 4 { "old" } else { "new" }, "app", "6");
+            e.insert(source::REALTIME_TIMESTAMP.to_string(), ts.to_string());
+            stat.take(&e);
+        }
+        assert_eq!(stat.recent.len(), 3);
+
+        stat.slide(6);
+        stat.complete();
+        assert_eq!(stat.total_msgs, 2);
+        assert_eq!((stat.first_seen, stat.last_seen), (Some(4), Some(5)));
+        assert_eq!(ranking(&stat), vec![(2, "app", "new")]);
+        let histogram = stat.builtin::<Histogram>().unwrap();
+        assert_eq!(histogram.counts(), vec![1, 1]);
+    }
+
+    #[test]
+    fn priority_filter_applies_before_stats() {
+        let mut stat = stat(vec![
+            entry("disk failed", "kernel", "2"),
+            entry("retrying", "app", "4"),
+            entry("started", "app", "6"),
+        ]);
+        stat.set_filter_priority(Some("err".parse().unwrap()))
+            .n_frequent(5)
+            .parse()
+            .unwrap();
+
+        assert_eq!(stat.total_msgs, 1);
+        assert_eq!(ranking(&stat), vec![(1, "kernel", "disk failed")]);
+    }
+
+    #[test]
+    fn json_report_has_raw_counts() {
+        let mut stat = stat(vec![
+            entry("a", "app", "6"),
+            entry("a", "app", "6"),
+            entry("b", "cron", "3"),
+        ]);
+        stat.n_frequent(1).parse().unwrap();
+
+        let json = serde_json::to_value(stat.report_document()).unwrap();
+        assert_eq!(json["schema_version"], 1);
+        assert_eq!(json["totals"]["messages"], 3);
+        assert_eq!(json["per_process"][0]["process"], "app");
+        assert_eq!(json["per_process"][0]["messages"], 2);
+        assert_eq!(json["top_talkers"][0]["count"], 2);
+        assert_eq!(json["top_talkers"][0]["priority_name"], "info");
+        assert!(json.get("templates").is_none());
+    }
+
+    #[test]
+    fn entries_missing_fields_fall_back_or_are_counted_as_skipped() {
+        let mut kernel = entry("usb 1-1: new device", "", "4");
+        kernel.remove("_COMM");
+        kernel.insert("_TRANSPORT".to_string(), "kernel".to_string());
+        let mut stdout = entry("listening", "", "");
+        stdout.remove("_COMM");
+        stdout.remove("PRIORITY");
+        stdout.insert("_SYSTEMD_UNIT".to_string(), "web.service".to_string());
+        let mut no_message = entry("", "app", "6");
+        no_message.remove("MESSAGE");
+        let mut no_process = stdout.clone();
+        no_process.remove("_SYSTEMD_UNIT");
+        // Left out by the priority filter, so not counted as skipped.
+        let mut filtered = entry("", "app", "7");
+        filtered.remove("MESSAGE");
+
+        let mut stat = stat(vec![kernel, stdout, no_message, no_process, filtered]);
+        stat.n_frequent(5)
+            .set_filter_priority(Some("info".parse().unwrap()))
+            .parse()
+            .unwrap();
+
+        assert_eq!(stat.total_msgs, 2);
+        assert_eq!(ranking(&stat).len(), 2);
+        assert_eq!(stat.per_process()["kernel"], 1);
+        assert_eq!(stat.top_talkers()[0].1.priority, "6");
+        assert_eq!(
+            stat.fallbacks().reasons(),
+            [
+                ("No _COMM, process \"kernel\" from _TRANSPORT", 1),
+                ("No _COMM, process from _SYSTEMD_UNIT", 1),
+                ("No PRIORITY, counted as info", 1),
+                ("Skipped, no MESSAGE", 1),
+                ("Skipped, no field naming the process", 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn read_errors_are_returned() {
+        struct Failing;
+
+        impl EntrySource for Failing {
+            fn next_entry(&mut self) -> io::Result<Option<Entry>> {
+                Err(io::Error::new(io::ErrorKind::InvalidData, "corrupt entry"))
+            }
+        }
+
+        let mut stat = JournalStat::new(Path::new("test"), Box::new(Failing));
+        let err = stat.parse().err().unwrap();
+        assert!(matches!(err, Error::Io(_)));
+        assert_eq!(err.to_string(), "corrupt entry");
+    }
+
+    #[test]
+    fn report_names_non_utf8_inputs() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let input = Path::new(OsStr::from_bytes(b"/var/log/\xff.journal"));
+        let stat = JournalStat::new(input, Box::new(Vec::new().into_iter()));
+        assert_eq!(stat.report_document().input, "/var/log/\u{fffd}.journal");
+    }
+
+    #[test]
+    fn failing_to_push_matches_down_is_a_warning() {
+        struct Unfiltered(std::vec::IntoIter<Entry>);
+
+        impl EntrySource for Unfiltered {
+            fn next_entry(&mut self) -> io::Result<Option<Entry>> {
+                Ok(self.0.next())
+            }
+
+            fn add_matches(&mut self, _groups: &[Vec<(&str, &str)>]) -> io::Result<bool> {
+                Err(io::Error::new(io::ErrorKind::InvalidInput, "bad match"))
+            }
+        }
+
+        let entries = vec![entry("a", "sshd", "6"), entry("b", "cron", "6")];
+        let warnings = Rc::new(RefCell::new(Vec::new()));
+        let seen = warnings.clone();
+        let mut stat =
+            JournalStat::new(Path::new("test"), Box::new(Unfiltered(entries.into_iter())));
+        stat.set_filter_matches(Some(MatchExpr::parse(&["_COMM=sshd"]).unwrap()))
+            .on_warning(Box::new(move |w| seen.borrow_mut().push(w.to_string())));
+        stat.parse().unwrap();
+
+        assert_eq!(stat.total_messages(), 1);
+        assert_eq!(
+            *warnings.borrow(),
+            ["filtering in the journal failed: bad match"]
+        );
+    }
+}
@@ -1,32 +1,80 @@
-use regex::Regex;
 /// Crude tool to parse systemd journal files in binary
 /// format in order to derive some statistics out of the
 /// messages.
 ///
 /// License: MIT
+use chrono::Local;
+use journalstat::{
+    analyzer::Builtin,
+    boot::{self, BootSpec},
+    burst::{BurstConfig, BurstKey, Rate},
+    cardinality,
+    exporter::{self, Metrics},
+    filter::MatchExpr,
+    histogram::Interval,
+    priority::PriorityRange,
+    report::OutputFormat,
+    report_boots, report_diff,
+    rules::{self, Rule},
+    source::{self, Backend, InputFormat},
+    template::ClusterMode,
+    time, Error, JournalStat, Refresh, Result,
+};
+use regex::Regex;
 use std::{
-    collections::HashMap,
-    hash::Hash,
+    io::{self, IsTerminal, Write},
+    net::TcpListener,
+    num::NonZeroUsize,
     path::{Path, PathBuf},
+    process,
+    time::Duration,
 };
 use structopt::StructOpt;
-use systemd::{
-    journal::{OpenDirectoryOptions, OpenFilesOptions},
-    Journal,
-};
-use tabled::{Table, Tabled};
+
+/// Messages listed per section of a diff, unless --top-talkers is given.
+const DIFF_MESSAGES: usize = 10;
+
+/// Templates reported by --cluster unless --top-talkers is given.
+const TEMPLATE_ROWS: usize = 10;
+
+/// Values reported by each --analyze statistic unless --top-talkers is given.
+const ANALYZER_ROWS: usize = 10;
+
+/// Number of templates the exporter serves unless --top-talkers is given.
+const EXPORTER_TEMPLATES: usize = 20;
+
+/// Longest wait for new entries when following the journal.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often --follow refreshes the report unless --refresh is given.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(2);
 
 #[derive(Debug, StructOpt)]
 #[structopt(name = "Journalstat", about = "Command line options")]
 struct Opt {
-    /// Input journal file or directory.
+    /// Input journal file or directory, or "-" to read from stdin.
     #[structopt(short, long, parse(from_os_str))]
     input: PathBuf,
 
+    /// Format of the input, one of "auto", "journal", "export" or "json".
+    #[structopt(long)]
+    input_format: Option<InputFormat>,
+
+    /// Journal reader to use, either "libsystemd" or "native".
+    #[structopt(long)]
+    backend: Option<Backend>,
+
     /// The number of top talkers to report on.
     #[structopt(short, long)]
     top_talkers: Option<usize>,
 
+    /// Group messages differing only in numbers, addresses, IDs, paths and times
+    /// into templates, "mask" to replace those with placeholders or "drain" to
+    /// also merge similar messages. Reports as many templates as --top-talkers,
+    /// or 10.
+    #[structopt(short, long)]
+    cluster: Option<ClusterMode>,
+
     /// The number of large messages to report on.
     #[structopt(short, long)]
     large_messages: Option<usize>,
@@ -38,273 +86,450 @@ struct Opt {
     /// Filter messages based on this regex pattern.
     #[structopt(short, long)]
     pattern: Option<String>,
-}
 
-#[derive(Eq, PartialEq, Hash, Clone)]
-struct Message {
-    /// Message contents.
-    msg: String,
-    /// The process that generated the message.
-    process: String,
-    /// Priority the message was sent at.
-    priority: String,
-}
+    /// Filter on fields with FIELD=VALUE, FIELD!=VALUE, FIELD~REGEX or FIELD!~REGEX.
+    /// Repeat to AND terms (terms on the same field with = are ORed) and pass "+"
+    /// between terms to OR the groups either side.
+    #[structopt(short, long = "match", number_of_values = 1)]
+    matches: Vec<String>,
+
+    /// Filter on priority, a level by number or name to include it and everything
+    /// more important, or a range such as "warning..emerg".
+    #[structopt(short = "P", long)]
+    priority: Option<PriorityRange>,
+
+    /// Only include entries at or after this time, e.g. "2023-05-01 12:00", "yesterday" or "-2h".
+    #[structopt(short = "S", long)]
+    since: Option<String>,
+
+    /// Only include entries at or before this time, accepts the same formats as --since.
+    #[structopt(short = "U", long)]
+    until: Option<String>,
+
+    /// Only include entries from this boot, an offset like journalctl (0 is the
+    /// last boot, -1 the one before) or a boot ID.
+    #[structopt(short, long, allow_hyphen_values = true)]
+    boot: Option<BootSpec>,
+
+    /// List the boots in the journal and exit.
+    #[structopt(long)]
+    list_boots: bool,
+
+    /// Break the per process message allocations down by boot.
+    #[structopt(long)]
+    per_boot: bool,
+
+    /// Report the bytes logged per process, unit and field.
+    #[structopt(long)]
+    footprint: bool,
+
+    /// List every field seen with the entries carrying it, its bytes and the
+    /// processes writing it most.
+    #[structopt(long)]
+    inventory: bool,
+
+    /// Report how many distinct values MESSAGE, _PID, _COMM, _SYSTEMD_UNIT and
+    /// _HOSTNAME take, overall and per process. Counts are exact up to 1024
+    /// values and estimated beyond.
+    #[structopt(long)]
+    cardinality: bool,
+
+    /// Also count the distinct values of this field, implies --cardinality.
+    /// Repeat to count several fields.
+    #[structopt(long = "cardinality-field", value_name = "field", number_of_values = 1)]
+    cardinality_fields: Vec<String>,
+
+    /// Count messages over time in intervals of "minute", "hour", "day" or a
+    /// duration such as "15min", overall and for the busiest processes.
+    #[structopt(long)]
+    histogram: Option<Interval>,
+
+    /// Flag bursts of at least this many messages per interval from one
+    /// process or template, e.g. "1000/min".
+    #[structopt(long)]
+    burst_rate: Option<Rate>,
+
+    /// Flag bursts of at least this many times the usual rate of a process or
+    /// template, counted per --burst-rate interval or per minute.
+    #[structopt(long)]
+    burst_factor: Option<f64>,
+
+    /// Track bursts per "process" or per message "template".
+    #[structopt(long)]
+    burst_by: Option<BurstKey>,
+
+    /// Add a statistic: "units" or "hosts" for the messages per unit or host,
+    /// or "field:NAME" for the messages per value of any field. Reports as
+    /// many values as --top-talkers, or 10.
+    #[structopt(long = "analyze", number_of_values = 1)]
+    analyzers: Vec<Builtin>,
+
+    /// Exit with status 1 if a rule is broken: "share > 20%", "messages > N",
+    /// "process:NAME > N", "priority:LEVEL > N" or "match:REGEX > N". Repeat
+    /// to check several rules.
+    #[structopt(long = "rule", number_of_values = 1)]
+    rules: Vec<Rule>,
+
+    /// Read rules from a file, one per line, lines starting with "#" being
+    /// comments.
+    #[structopt(long, parse(from_os_str))]
+    rules_file: Option<PathBuf>,
+
+    /// Count messages approximately in bounded memory, tracking at most this
+    /// many distinct messages. Counts are then upper bounds, reported with
+    /// the most they may be over.
+    #[structopt(long)]
+    approximate: Option<NonZeroUsize>,
+
+    /// Read the files of a directory input on this many threads at once, 0
+    /// for one per CPU. Bursts, Drain templates and --follow still read on
+    /// one thread.
+    #[structopt(short, long)]
+    jobs: Option<usize>,
 
-struct JournalStat {
-    // Input file/directory for debug purposes.
-    input: PathBuf,
-    // Filtering on a systemd unit.
-    unit: Option<String>,
-    // Handle to the journal.
-    journal: Journal,
-    // Map of messages in the journal to a frequency.
-    msg_freq: HashMap<Message, u32>,
-    // List of most frequent messages in the journal.
-    top_talkers: Vec<(u32, Message)>,
-    // The largest messages in the journal.
-    largest: Vec<String>,
-    // Per process % of messages.
-    per_process: HashMap<String, u32>,
-    // Total number of messages parsed.
-    total_msgs: u64,
-    // Regex to match on.
-    regex: Option<Regex>,
-}
+    /// Output format, one of "table", "json", "csv" or "markdown".
+    #[structopt(long)]
+    format: Option<OutputFormat>,
 
-#[derive(Tabled)]
-#[allow(non_snake_case)]
-struct TopTalkerTableEntry<'a> {
-    Rank: usize,
-    Frequency: u32,
-    Process: &'a str,
-    Priority: String,
-    Message: &'a str,
-}
+    /// Browse the statistics in an interactive terminal UI.
+    #[cfg(feature = "tui")]
+    #[structopt(long)]
+    tui: bool,
 
-#[derive(Tabled)]
-#[allow(non_snake_case)]
-struct PerProcessTableEntry<'a> {
-    Rank: usize,
-    Process: &'a str,
-    Percent: String,
-}
+    /// After reading the journal, keep following it like journalctl -f and
+    /// refresh the report as entries are written.
+    #[structopt(short, long)]
+    follow: bool,
 
-#[derive(Tabled)]
-#[allow(non_snake_case)]
-struct SizeTableEntry<'a> {
-    Rank: usize,
-    Size: usize,
-    Message: &'a str,
-}
+    /// How often --follow refreshes the report, e.g. "10s", 2s by default.
+    #[structopt(long, requires = "follow")]
+    refresh: Option<Interval>,
 
-impl JournalStat {
-    /// Create a new JournalStat struct.
-    pub fn new(path: &Path) -> Result<Self, systemd::Error> {
-        let journal = if path.is_dir() {
-            OpenDirectoryOptions::default()
-                .open_directory(path.as_os_str().to_str().expect("failed to parse string"))
-        } else {
-            OpenFilesOptions::default().open_files(path.as_os_str().to_str())
-        }?;
-
-        Ok(Self {
-            input: path.to_path_buf(),
-            journal,
-            unit: None,
-            msg_freq: HashMap::new(),
-            top_talkers: Vec::with_capacity(10),
-            largest: Vec::with_capacity(10),
-            per_process: HashMap::new(),
-            total_msgs: 0,
-            regex: None,
-        })
-    }
+    /// Report on the entries of the last duration only, e.g. "5min", rather
+    /// than on everything read while following.
+    #[structopt(long, requires = "follow")]
+    window: Option<Interval>,
 
-    /// Set the regex to filter on.
-    pub fn set_regex(&mut self, regex: &Option<Regex>) -> &mut Self {
-        self.regex = regex.clone();
-        self
-    }
+    #[structopt(subcommand)]
+    cmd: Option<Command>,
+}
 
-    /// Filter on a particular systemd unit.
-    pub fn set_filter_unit(&mut self, unit: &Option<String>) -> &mut Self {
-        self.unit = unit.clone();
-        self
-    }
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// Compare the messages of --input, within --since and --until, against
+    /// another journal or another time window.
+    Diff(DiffOpt),
+    /// Follow --input and serve Prometheus metrics on the messages read,
+    /// counting as many templates as --top-talkers.
+    Exporter(ExporterOpt),
+}
 
-    /// Set the number of top talkers to watch for.
-    pub fn n_frequent(&mut self, n_freq: usize) -> &mut Self {
-        self.top_talkers = Vec::with_capacity(n_freq);
-        self
-    }
+#[derive(Debug, StructOpt)]
+struct DiffOpt {
+    /// Journal to compare against, --input by default.
+    #[structopt(short, long, parse(from_os_str))]
+    against: Option<PathBuf>,
 
-    /// Set the top number of large messages to record.
-    pub fn n_largest(&mut self, n_largest: usize) -> &mut Self {
-        self.largest = Vec::with_capacity(n_largest);
-        self
-    }
+    /// Start of the window to compare against. Without this and
+    /// --against-until the window is --since to --until.
+    #[structopt(long)]
+    against_since: Option<String>,
 
-    /// Read the journal and record any statistics.
-    pub fn parse(&mut self) -> &mut Self {
-        while let Ok(Some(entry)) = self.journal.next_entry() {
-            if let (Some(msg), Some(process_name), Some(priority)) = (
-                entry.get("MESSAGE"),
-                entry.get("_COMM"),
-                entry.get("PRIORITY"),
-            ) {
-                if let Some(unit) = &self.unit {
-                    if let Some(junit) = entry.get("_SYSTEMD_UNIT") {
-                        if !unit.eq(junit) {
-                            continue;
-                        }
-                    }
-                }
-
-                if let Some(regex) = &self.regex {
-                    if regex.find(&msg).is_none() {
-                        continue;
-                    }
-                }
-
-                self.total_msgs += 1;
-
-                let key = Message {
-                    msg: msg.clone(),
-                    process: process_name.clone(),
-                    priority: priority.clone(),
-                };
+    /// End of the window to compare against.
+    #[structopt(long)]
+    against_until: Option<String>,
+}
+
+#[derive(Debug, StructOpt)]
+struct ExporterOpt {
+    /// Address to serve /metrics on.
+    #[structopt(short, long, default_value = "127.0.0.1:9934")]
+    listen: String,
+}
 
-                // No way around the to_string() which will hurt performance.
-                let count = *self
-                    .msg_freq
-                    .entry(key.clone())
-                    .and_modify(|c| *c += 1)
-                    .or_insert(1);
-
-                // Update per process stats.
-                self.per_process
-                    .entry(process_name.to_string())
-                    .and_modify(|c| *c += 1)
-                    .or_insert(1);
-
-                // Keep track of the top talkers...
-                for i in 0..self.top_talkers.capacity() {
-                    if let Some((ttcount, _)) = self.top_talkers.get(i) {
-                        if count > *ttcount {
-                            self.top_talkers[i] = (count, key);
-                            break;
-                        }
-                    } else {
-                        self.top_talkers.push((count, key));
-                        break;
-                    }
-                }
-
-                // Keep track of the big messages.
-                for i in 0..self.largest.capacity() {
-                    if let Some(lmsg) = self.largest.get(i) {
-                        if msg.len() > lmsg.len() {
-                            self.largest[i] = msg.clone();
-                            break;
-                        }
-                    } else {
-                        self.largest.push(msg.clone());
-                    }
-                }
+/// Open an input and set up the statistics for it, with the filters and
+/// reports asked for on the command line.
+fn journal_stat(
+    opt: &Opt,
+    input: &Path,
+    since: Option<u64>,
+    until: Option<u64>,
+) -> Result<JournalStat> {
+    let open = || {
+        JournalStat::open(
+            input,
+            opt.input_format.unwrap_or_default(),
+            opt.backend.unwrap_or_default(),
+        )
+    };
+
+    // Offsets are relative to the boots in the input, which takes a first
+    // pass over it to find.
+    let boot = match &opt.boot {
+        None => None,
+        Some(BootSpec::Id(id)) => Some(id.clone()),
+        Some(BootSpec::Offset(offset)) => {
+            if input == Path::new("-") {
+                return Err(Error::Invalid(
+                    "boot offsets cannot be used with stdin, give a boot ID instead".to_string(),
+                ));
             }
+            let boots = list_boots(opt, input)?;
+            Some(boot::resolve_offset(*offset, &boots).map_err(Error::Invalid)?)
         }
-
-        self
-    }
-
-    /// Turn a number string priority into a syslog priority name.
-    fn pretty_priorty(&self, prio: &str) -> String {
-        match prio {
-            "0" => "emergency",
-            "1" => "alert",
-            "2" => "critical",
-            "3" => "error",
-            "4" => "warn",
-            "5" => "notice",
-            "6" => "info",
-            "7" => "debug",
-            _ => "unknown",
+    };
+
+    let matches = if opt.matches.is_empty() {
+        None
+    } else {
+        Some(
+            MatchExpr::parse(&opt.matches)
+                .map_err(|e| Error::Invalid(format!("invalid --match: {}", e)))?,
+        )
+    };
+    let regex = match &opt.pattern {
+        Some(pattern) => Some(
+            Regex::new(pattern).map_err(|e| Error::Invalid(format!("invalid --pattern: {}", e)))?,
+        ),
+        None => None,
+    };
+
+    let cardinality = (opt.cardinality || !opt.cardinality_fields.is_empty()).then(|| {
+        let mut fields: Vec<String> = cardinality::DEFAULT_FIELDS
+            .iter()
+            .map(|f| f.to_string())
+            .collect();
+        for field in &opt.cardinality_fields {
+            if !fields.contains(field) {
+                fields.push(field.clone());
+            }
         }
-        .to_string()
+        fields
+    });
+
+    let mut stat = open()?;
+    stat.n_frequent(opt.top_talkers.unwrap_or(0))
+        .n_largest(opt.large_messages.unwrap_or(0))
+        .approximate(opt.approximate)
+        .cluster(opt.cluster)
+        .n_templates(opt.top_talkers.unwrap_or(TEMPLATE_ROWS))
+        .set_filter_unit(&opt.unit)
+        .set_filter_matches(matches)
+        .set_filter_priority(opt.priority)
+        .set_filter_boot(&boot)
+        .per_boot(opt.per_boot)
+        .footprint(opt.footprint)
+        .inventory(opt.inventory)
+        .cardinality(cardinality)
+        .histogram(opt.histogram)
+        .detect_bursts(BurstConfig {
+            rate: opt.burst_rate,
+            factor: opt.burst_factor,
+            key: opt.burst_by.unwrap_or_default(),
+        })
+        .set_time_range(since, until)
+        .set_regex(&regex)
+        .sliding_window(opt.window)
+        .output_format(opt.format.unwrap_or_default())
+        .on_warning(Box::new(|warning| eprintln!("journalstat: {}", warning)));
+    for analyzer in &opt.analyzers {
+        stat.analyzer(analyzer.create(opt.top_talkers.unwrap_or(ANALYZER_ROWS)));
     }
+    Ok(stat)
+}
 
-    /// Generate a report.
-    pub fn report(&self) {
-        println!("Journal statistics for {}", self.input.display());
+/// Read the input, on several threads if asked to.
+fn parse(opt: &Opt, stat: &mut JournalStat) -> Result<()> {
+    match opt.jobs {
+        Some(jobs) => stat.parse_parallel(opt.backend.unwrap_or_default(), jobs)?,
+        None => stat.parse()?,
+    };
+    Ok(())
+}
 
-        if !self.per_process.is_empty() {
-            println!("Per process message allocations");
+/// The boots in `input`.
+fn list_boots(opt: &Opt, input: &Path) -> Result<Vec<boot::Boot>> {
+    let mut journal = source::open(
+        input,
+        opt.input_format.unwrap_or_default(),
+        opt.backend.unwrap_or_default(),
+    )
+    .map_err(|source| Error::Open {
+        path: input.to_path_buf(),
+        source,
+    })?;
+    Ok(boot::list_boots(&mut *journal)?)
+}
 
-            let mut pp_vec: Vec<(String, u32)> =
-                self.per_process.clone().into_iter().map(|e| e).collect();
-            pp_vec.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+fn main() {
+    match run(&Opt::from_args()) {
+        Ok(true) => {}
+        // Rules were broken, already reported.
+        Ok(false) => process::exit(1),
+        // The output was closed early, e.g. piped into head.
+        Err(Error::Io(e)) if e.kind() == io::ErrorKind::BrokenPipe => {}
+        Err(e) => {
+            eprintln!("journalstat: {}", e);
+            process::exit(2);
+        }
+    }
+}
 
-            let mut table = Vec::new();
+/// Run the command line, returning false if a rule was broken.
+fn run(opt: &Opt) -> Result<bool> {
+    let mut stdout = io::stdout().lock();
 
-            for (i, (process, nmsgs)) in pp_vec.iter().enumerate() {
-                table.push(PerProcessTableEntry {
-                    Rank: i + 1,
-                    Process: process,
-                    Percent: format!("{:.02}", ((*nmsgs as f32 / self.total_msgs as f32) * 100.0)),
-                });
-            }
+    if opt.list_boots {
+        report_boots(&mut stdout, &list_boots(opt, &opt.input)?)?;
+        return Ok(true);
+    }
 
-            println!("{}", Table::new(table));
+    let now = Local::now();
+    let parse_time = |s: &Option<String>, what: &str| {
+        s.as_ref()
+            .map(|s| {
+                time::parse_time(s, now)
+                    .map_err(|e| Error::Invalid(format!("invalid {} time: {}", what, e)))
+            })
+            .transpose()
+    };
+    let since = parse_time(&opt.since, "--since")?;
+    let until = parse_time(&opt.until, "--until")?;
+
+    // Rules are checked once a journal has been read through, which these
+    // never do.
+    if !opt.rules.is_empty() || opt.rules_file.is_some() {
+        let mode = match &opt.cmd {
+            Some(Command::Diff(_)) => Some("diff"),
+            Some(Command::Exporter(_)) => Some("exporter"),
+            #[cfg(feature = "tui")]
+            None if opt.tui => Some("--tui"),
+            None if opt.follow => Some("--follow"),
+            None => None,
+        };
+        if let Some(mode) = mode {
+            return Err(Error::Invalid(format!(
+                "--rule and --rules-file cannot be used with {}",
+                mode
+            )));
         }
+    }
 
-        if !self.top_talkers.is_empty() {
-            println!("Top {} most frequent messages:", self.top_talkers.len());
+    match &opt.cmd {
+        #[cfg(feature = "tui")]
+        None if opt.tui => {
+            let mut stat = journal_stat(opt, &opt.input, since, until)?;
+            parse(opt, &mut stat)?;
+            journalstat::tui::run(&stat)?;
+        }
+        None if opt.follow => {
+            let every = opt
+                .refresh
+                .map_or(REFRESH_INTERVAL, |r| Duration::from_micros(r.0));
+            let redraw = stdout.is_terminal();
+
+            let mut stat = journal_stat(opt, &opt.input, since, until)?;
+            stat.follow(
+                POLL_INTERVAL.min(every),
+                Some(Refresh {
+                    every,
+                    out: &mut stdout,
+                    redraw,
+                }),
+            )?;
+            stat.report(&mut stdout)?;
+        }
+        None => {
+            let mut rules = Vec::new();
+            if let Some(path) = &opt.rules_file {
+                rules = rules::load(path)
+                    .map_err(|e| Error::Invalid(format!("invalid rules: {}", e)))?;
+            }
+            // Refused before reading rather than after.
+            let exact = opt.rules.iter().chain(&rules).any(Rule::needs_exact_counts);
+            if exact && opt.approximate.is_some() {
+                return Err(Error::Invalid(
+                    "priority and match rules need exact message counts, they cannot be used with --approximate"
+                        .to_string(),
+                ));
+            }
 
-            let mut table = Vec::new();
+            let mut stat = journal_stat(opt, &opt.input, since, until)?;
+            parse(opt, &mut stat)?;
+            stat.report(&mut stdout)?;
+            stdout.flush()?;
 
-            for (i, (count, msg)) in self.top_talkers.iter().enumerate() {
-                table.push(TopTalkerTableEntry {
-                    Rank: i + 1,
-                    Frequency: *count,
-                    Process: &msg.process,
-                    Priority: self.pretty_priorty(&msg.priority),
-                    Message: &msg.msg,
-                });
+            let mut violations = Vec::new();
+            for rule in opt.rules.iter().chain(&rules) {
+                violations.extend(rule.check(&stat)?);
             }
-
-            println!("{}", Table::new(table));
+            for violation in &violations {
+                eprintln!("{}", violation);
+            }
+            return Ok(violations.is_empty());
         }
+        Some(Command::Diff(diff)) => {
+            let against = diff.against.as_deref().unwrap_or(&opt.input);
+            if diff.against.is_none()
+                && diff.against_since.is_none()
+                && diff.against_until.is_none()
+            {
+                return Err(Error::Invalid(
+                    "nothing to compare, give --against, --against-since or --against-until"
+                        .to_string(),
+                ));
+            }
+            if opt.approximate.is_some() {
+                return Err(Error::Invalid(
+                    "diff needs exact message counts, it cannot be used with --approximate"
+                        .to_string(),
+                ));
+            }
+            if opt.input == Path::new("-") && against == Path::new("-") {
+                return Err(Error::Invalid(
+                    "stdin can only be read once, compare it against a file".to_string(),
+                ));
+            }
 
-        if !self.largest.is_empty() {
-            println!("Top {} largest messages:", self.largest.len());
-
-            let mut table = Vec::new();
+            let mut before = journal_stat(opt, &opt.input, since, until)?;
+            parse(opt, &mut before)?;
+            // A window given for the other side replaces --since and --until
+            // as a whole.
+            let (against_since, against_until) =
+                if diff.against_since.is_some() || diff.against_until.is_some() {
+                    (
+                        parse_time(&diff.against_since, "--against-since")?,
+                        parse_time(&diff.against_until, "--against-until")?,
+                    )
+                } else {
+                    (since, until)
+                };
 
-            for (i, msg) in self.largest.iter().enumerate() {
-                table.push(SizeTableEntry {
-                    Rank: i + 1,
-                    Size: msg.len(),
-                    Message: msg,
-                });
-            }
+            let mut after = journal_stat(opt, against, against_since, against_until)?;
+            parse(opt, &mut after)?;
 
-            println!("{}", Table::new(table));
+            report_diff(
+                &mut stdout,
+                &before,
+                &after,
+                opt.top_talkers.unwrap_or(DIFF_MESSAGES),
+                opt.format.unwrap_or_default(),
+            )?;
+        }
+        Some(Command::Exporter(exporter)) => {
+            let listener = TcpListener::bind(&exporter.listen).map_err(|e| {
+                Error::Invalid(format!("cannot listen on {}: {}", exporter.listen, e))
+            })?;
+            let metrics = Metrics::new(opt.top_talkers.unwrap_or(EXPORTER_TEMPLATES));
+            exporter::serve(listener, metrics.clone(), |e| {
+                eprintln!("journalstat: exporter: {}", e)
+            });
+
+            journal_stat(opt, &opt.input, since, until)?
+                .export(Some(metrics))
+                .follow(POLL_INTERVAL, None)?;
         }
     }
-}
 
-fn main() {
-    let opt = Opt::from_args();
-
-    JournalStat::new(&opt.input)
-        .expect("failed to create new journal stat struct")
-        .n_frequent(opt.top_talkers.unwrap_or(0))
-        .n_largest(opt.large_messages.unwrap_or(0))
-        .set_filter_unit(&opt.unit)
-        .set_regex(
-            &opt.pattern
-                .map_or(None, |r| Some(Regex::new(&r).expect("invalid regex"))),
-        )
-        .parse()
-        .report();
+    Ok(true)
 }
@@ -0,0 +1,439 @@
+//! The statistics always gathered: how often every distinct message was
+//! logged, the messages per process and the largest messages.
+
+use crate::{
+    analyzer::{self, Analyzer, Section},
+    heavy::SpaceSaving,
+    priority,
+    report::{self, OutputFormat, Report},
+    source::Entry,
+    Message,
+};
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    io::{self, Write},
+    num::NonZeroUsize,
+};
+use tabled::Tabled;
+
+/// Times every distinct message was logged, and the most frequent of them.
+pub struct Messages {
+    // Number of top talkers to report on.
+    top: usize,
+    // Map of messages in the journal to a frequency.
+    msg_freq: HashMap<Message, u32>,
+    // Bounded approximate counts, filling msg_freq when ranking instead.
+    heavy_hitters: Option<SpaceSaving<Message>>,
+    // List of most frequent messages in the journal, ranked before reporting.
+    top_talkers: Vec<(u32, Message)>,
+}
+
+#[derive(Tabled)]
+#[allow(non_snake_case)]
+struct TopTalkerTableEntry<'a> {
+    Rank: usize,
+    Frequency: u32,
+    Process: &'a str,
+    Priority: String,
+    Message: &'a str,
+}
+
+impl Messages {
+    /// Count messages, exactly or tracking at most `counters` distinct
+    /// messages, and rank the `top` most frequent.
+    pub fn new(top: usize, counters: Option<NonZeroUsize>) -> Self {
+        Self {
+            top,
+            msg_freq: HashMap::new(),
+            heavy_hitters: counters.map(SpaceSaving::new),
+            top_talkers: Vec::new(),
+        }
+    }
+
+    /// Times every distinct message was counted.
+    pub fn counts(&self) -> &HashMap<Message, u32> {
+        &self.msg_freq
+    }
+
+    /// The most frequent messages and their counts, most frequent first.
+    pub fn top_talkers(&self) -> &[(u32, Message)] {
+        &self.top_talkers
+    }
+
+    /// Whether messages are counted approximately.
+    pub fn is_approximate(&self) -> bool {
+        self.heavy_hitters.is_some()
+    }
+
+    /// Bounds on the counts, when counting approximately.
+    pub fn approximation(&self) -> Option<report::Approximation> {
+        self.heavy_hitters
+            .as_ref()
+            .map(|heavy_hitters| report::Approximation {
+                counters: heavy_hitters.capacity(),
+                max_error: heavy_hitters.max_error(),
+            })
+    }
+}
+
+impl Analyzer for Messages {
+    fn name(&self) -> &str {
+        "top_talkers"
+    }
+
+    fn observe(&mut self, message: &Message, _entry: &Entry) {
+        match &mut self.heavy_hitters {
+            Some(heavy_hitters) => heavy_hitters.add(message),
+            None => {
+                self.msg_freq
+                    .entry(message.clone())
+                    .and_modify(|c| *c += 1)
+                    .or_insert(1);
+            }
+        }
+    }
+
+    fn fork(&self) -> Box<dyn Analyzer> {
+        let counters = self
+            .heavy_hitters
+            .as_ref()
+            .and_then(|h| NonZeroUsize::new(h.capacity()));
+        Box::new(Self::new(self.top, counters))
+    }
+
+    fn merge(&mut self, other: Box<dyn Analyzer>) -> crate::Result<()> {
+        let other = analyzer::downcast::<Self>(other)?;
+        for (message, count) in other.msg_freq {
+            *self.msg_freq.entry(message).or_insert(0) += count;
+        }
+        if let (Some(mine), Some(theirs)) = (&mut self.heavy_hitters, other.heavy_hitters) {
+            mine.merge(theirs);
+        }
+        Ok(())
+    }
+
+    /// Rank the most frequent messages from the complete frequency map,
+    /// filled from the tracked counts first when counting approximately.
+    ///
+    /// Messages are ordered by descending frequency, ties are broken by the
+    /// message, process and priority so the ranking is deterministic.
+    fn complete(&mut self) {
+        if let Some(heavy_hitters) = &self.heavy_hitters {
+            self.msg_freq = heavy_hitters
+                .counters()
+                .iter()
+                .map(|c| (c.item.clone(), u32::try_from(c.count).unwrap_or(u32::MAX)))
+                .collect();
+        }
+
+        let mut ranked: Vec<(u32, Message)> = self
+            .msg_freq
+            .iter()
+            .map(|(msg, count)| (*count, msg.clone()))
+            .collect();
+
+        ranked.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+        ranked.truncate(self.top);
+
+        self.top_talkers = ranked;
+    }
+
+    fn finish(&self) -> Section {
+        Section {
+            name: self.name().to_string(),
+            title: format!("Top {} most frequent messages:", self.top_talkers.len()),
+            columns: ["Rank", "Frequency", "Process", "Priority", "Message"]
+                .map(String::from)
+                .to_vec(),
+            rows: self
+                .top_talkers
+                .iter()
+                .enumerate()
+                .map(|(i, (count, msg))| {
+                    vec![
+                        (i + 1).into(),
+                        (*count).into(),
+                        msg.process.as_str().into(),
+                        priority::name(&msg.priority)
+                            .unwrap_or(&msg.priority)
+                            .into(),
+                        msg.msg.as_str().into(),
+                    ]
+                })
+                .collect(),
+        }
+    }
+
+    fn report<'a>(&'a self, document: &mut Report<'a>) {
+        document.totals.distinct_messages =
+            self.heavy_hitters.is_none().then_some(self.msg_freq.len());
+        document.approximate = self.approximation();
+        document.top_talkers = self
+            .top_talkers
+            .iter()
+            .enumerate()
+            .map(|(i, (count, msg))| report::TopTalker {
+                rank: i + 1,
+                count: *count,
+                process: &msg.process,
+                priority: &msg.priority,
+                priority_name: priority::name(&msg.priority).unwrap_or(&msg.priority),
+                message: &msg.msg,
+                error: self
+                    .heavy_hitters
+                    .as_ref()
+                    .and_then(|h| h.get(msg))
+                    .map(|c| c.error),
+            })
+            .collect();
+    }
+
+    fn write_tables(&self, format: OutputFormat, out: &mut dyn Write) -> io::Result<()> {
+        if self.top_talkers.is_empty() {
+            return Ok(());
+        }
+
+        let table = self
+            .top_talkers
+            .iter()
+            .enumerate()
+            .map(|(i, (count, msg))| TopTalkerTableEntry {
+                Rank: i + 1,
+                Frequency: *count,
+                Process: &msg.process,
+                Priority: priority::name(&msg.priority)
+                    .unwrap_or(&msg.priority)
+                    .to_string(),
+                Message: &msg.msg,
+            })
+            .collect();
+
+        writeln!(
+            out,
+            "{}",
+            report::table(
+                format,
+                &format!("Top {} most frequent messages:", self.top_talkers.len()),
+                table
+            )
+        )
+    }
+}
+
+/// Messages logged per process.
+#[derive(Debug, Default)]
+pub struct Processes {
+    per_process: HashMap<String, u32>,
+    total: u64,
+}
+
+#[derive(Tabled)]
+#[allow(non_snake_case)]
+struct PerProcessTableEntry<'a> {
+    Rank: usize,
+    Process: &'a str,
+    Percent: String,
+}
+
+impl Processes {
+    /// Messages counted per process.
+    pub fn counts(&self) -> &HashMap<String, u32> {
+        &self.per_process
+    }
+}
+
+impl Analyzer for Processes {
+    fn name(&self) -> &str {
+        "per_process"
+    }
+
+    fn observe(&mut self, message: &Message, _entry: &Entry) {
+        self.per_process
+            .entry(message.process.clone())
+            .and_modify(|c| *c += 1)
+            .or_insert(1);
+        self.total += 1;
+    }
+
+    fn fork(&self) -> Box<dyn Analyzer> {
+        Box::<Self>::default()
+    }
+
+    fn merge(&mut self, other: Box<dyn Analyzer>) -> crate::Result<()> {
+        let other = analyzer::downcast::<Self>(other)?;
+        for (process, count) in other.per_process {
+            *self.per_process.entry(process).or_insert(0) += count;
+        }
+        self.total += other.total;
+        Ok(())
+    }
+
+    fn finish(&self) -> Section {
+        Section {
+            name: self.name().to_string(),
+            title: "Per process message allocations".to_string(),
+            columns: ["Rank", "Process", "Messages", "Percent"]
+                .map(String::from)
+                .to_vec(),
+            rows: report::process_shares(&self.per_process, self.total)
+                .into_iter()
+                .map(|share| {
+                    vec![
+                        share.rank.into(),
+                        share.process.into(),
+                        share.messages.into(),
+                        ((share.percent * 100.0).round() / 100.0).into(),
+                    ]
+                })
+                .collect(),
+        }
+    }
+
+    fn report<'a>(&'a self, document: &mut Report<'a>) {
+        document.totals.processes = self.per_process.len();
+        document.per_process = report::process_shares(&self.per_process, self.total);
+    }
+
+    fn write_tables(&self, format: OutputFormat, out: &mut dyn Write) -> io::Result<()> {
+        if self.per_process.is_empty() {
+            return Ok(());
+        }
+
+        let table = report::process_shares(&self.per_process, self.total)
+            .into_iter()
+            .map(|share| PerProcessTableEntry {
+                Rank: share.rank,
+                Process: share.process,
+                Percent: format!("{:.02}", share.percent),
+            })
+            .collect();
+
+        writeln!(
+            out,
+            "{}",
+            report::table(format, "Per process message allocations", table)
+        )
+    }
+}
+
+/// The largest distinct messages.
+#[derive(Debug, Default)]
+pub struct Largest {
+    // Number of messages kept, and the messages largest first.
+    top: usize,
+    largest: Vec<String>,
+}
+
+#[derive(Tabled)]
+#[allow(non_snake_case)]
+struct SizeTableEntry<'a> {
+    Rank: usize,
+    Size: usize,
+    Message: &'a str,
+}
+
+impl Largest {
+    /// Keep the `top` largest messages.
+    pub fn new(top: usize) -> Self {
+        Self {
+            top,
+            largest: Vec::new(),
+        }
+    }
+
+    /// The largest messages recorded.
+    pub fn messages(&self) -> &[String] {
+        &self.largest
+    }
+
+    /// Keep `msg` if it is among the largest distinct messages, ties broken
+    /// by the message so the result does not depend on read order.
+    fn keep(&mut self, msg: &str) {
+        if let Err(i) = self
+            .largest
+            .binary_search_by(|l| (Reverse(l.len()), l.as_str()).cmp(&(Reverse(msg.len()), msg)))
+        {
+            if i < self.top {
+                self.largest.insert(i, msg.to_string());
+                self.largest.truncate(self.top);
+            }
+        }
+    }
+}
+
+impl Analyzer for Largest {
+    fn name(&self) -> &str {
+        "largest"
+    }
+
+    fn observe(&mut self, message: &Message, _entry: &Entry) {
+        self.keep(&message.msg);
+    }
+
+    fn fork(&self) -> Box<dyn Analyzer> {
+        Box::new(Self::new(self.top))
+    }
+
+    fn merge(&mut self, other: Box<dyn Analyzer>) -> crate::Result<()> {
+        let other = analyzer::downcast::<Self>(other)?;
+        for msg in &other.largest {
+            self.keep(msg);
+        }
+        Ok(())
+    }
+
+    fn finish(&self) -> Section {
+        Section {
+            name: self.name().to_string(),
+            title: format!("Top {} largest messages:", self.largest.len()),
+            columns: ["Rank", "Size", "Message"].map(String::from).to_vec(),
+            rows: self
+                .largest
+                .iter()
+                .enumerate()
+                .map(|(i, msg)| vec![(i + 1).into(), msg.len().into(), msg.as_str().into()])
+                .collect(),
+        }
+    }
+
+    fn report<'a>(&'a self, document: &mut Report<'a>) {
+        document.largest = self
+            .largest
+            .iter()
+            .enumerate()
+            .map(|(i, msg)| report::LargeMessage {
+                rank: i + 1,
+                size: msg.len(),
+                message: msg,
+            })
+            .collect();
+    }
+
+    fn write_tables(&self, format: OutputFormat, out: &mut dyn Write) -> io::Result<()> {
+        if self.largest.is_empty() {
+            return Ok(());
+        }
+
+        let table = self
+            .largest
+            .iter()
+            .enumerate()
+            .map(|(i, msg)| SizeTableEntry {
+                Rank: i + 1,
+                Size: msg.len(),
+                Message: msg,
+            })
+            .collect();
+
+        writeln!(
+            out,
+            "{}",
+            report::table(
+                format,
+                &format!("Top {} largest messages:", self.largest.len()),
+                table
+            )
+        )
+    }
+}
@@ -0,0 +1,972 @@
+//! Pure Rust reader for the systemd journal file format.
+//!
+//! Only the parts of the format needed to walk every entry are implemented:
+//! the header, the entry array chain, ENTRY objects and the DATA objects they
+//! reference, including xz, lz4 and zstd compressed payloads. Hash tables,
+//! FIELD objects and sealing tags are not needed for a sequential read and are
+//! ignored. The layout is described at https://systemd.io/JOURNAL_FILE_FORMAT/.
+
+use crate::source::{self, Entry, EntrySource, REALTIME_TIMESTAMP};
+use inotify::{Inotify, WatchMask};
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    fs::{self, File},
+    io::{self, Read},
+    os::{
+        fd::AsRawFd,
+        unix::fs::{FileExt, MetadataExt},
+    },
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+const SIGNATURE: &[u8; 8] = b"LPKSHHRH";
+
+// Header incompatible flags.
+const HEADER_INCOMPATIBLE_COMPRESSED_XZ: u32 = 1 << 0;
+const HEADER_INCOMPATIBLE_COMPRESSED_LZ4: u32 = 1 << 1;
+const HEADER_INCOMPATIBLE_KEYED_HASH: u32 = 1 << 2;
+const HEADER_INCOMPATIBLE_COMPRESSED_ZSTD: u32 = 1 << 3;
+const HEADER_INCOMPATIBLE_COMPACT: u32 = 1 << 4;
+const HEADER_INCOMPATIBLE_SUPPORTED: u32 = HEADER_INCOMPATIBLE_COMPRESSED_XZ
+    | HEADER_INCOMPATIBLE_COMPRESSED_LZ4
+    | HEADER_INCOMPATIBLE_KEYED_HASH
+    | HEADER_INCOMPATIBLE_COMPRESSED_ZSTD
+    | HEADER_INCOMPATIBLE_COMPACT;
+
+// Object types.
+const OBJECT_DATA: u8 = 1;
+const OBJECT_ENTRY: u8 = 3;
+const OBJECT_ENTRY_ARRAY: u8 = 6;
+
+// Object flags.
+const OBJECT_COMPRESSED_XZ: u8 = 1 << 0;
+const OBJECT_COMPRESSED_LZ4: u8 = 1 << 1;
+const OBJECT_COMPRESSED_ZSTD: u8 = 1 << 2;
+
+// Offsets into the header.
+const HEADER_MIN_SIZE: u64 = 208;
+const HEADER_INCOMPATIBLE_FLAGS: usize = 12;
+const HEADER_HEADER_SIZE: usize = 88;
+const HEADER_ARENA_SIZE: usize = 96;
+const HEADER_N_ENTRIES: usize = 152;
+const HEADER_ENTRY_ARRAY_OFFSET: usize = 176;
+
+// Sizes of the fixed parts of objects.
+const OBJECT_HEADER_SIZE: u64 = 16;
+const DATA_OBJECT_SIZE: usize = 64;
+const DATA_OBJECT_COMPACT_SIZE: usize = 72;
+const ENTRY_OBJECT_SIZE: usize = 64;
+const ENTRY_ARRAY_OBJECT_SIZE: usize = 24;
+
+// Largest payload a DATA object can hold once decompressed, DATA_SIZE_MAX in
+// systemd's journal-file.h. Anything larger is corrupt or forged.
+const DATA_SIZE_MAX: usize = 768 * 1024 * 1024;
+
+fn invalid_data<E: std::fmt::Display>(err: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err.to_string())
+}
+
+/// Collects decompressed output, failing once it would exceed
+/// [`DATA_SIZE_MAX`].
+struct Capped(Vec<u8>);
+
+impl io::Write for Capped {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.0.len() + buf.len() > DATA_SIZE_MAX {
+            return Err(invalid_data("decompressed payload too large"));
+        }
+        self.0.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn le64(buf: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(buf[offset..offset + 8].try_into().unwrap())
+}
+
+fn le32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+/// Decompress a DATA object payload according to its object flags.
+fn decompress(flags: u8, payload: &[u8]) -> io::Result<Vec<u8>> {
+    if flags & OBJECT_COMPRESSED_XZ != 0 {
+        let mut out = Capped(Vec::new());
+        lzma_rs::xz_decompress(&mut io::BufReader::new(payload), &mut out)
+            .map_err(|e| invalid_data(format!("xz: {:?}", e)))?;
+        Ok(out.0)
+    } else if flags & OBJECT_COMPRESSED_LZ4 != 0 {
+        // journald prefixes the lz4 block with the uncompressed size.
+        if payload.len() < 8 {
+            return Err(invalid_data("lz4: truncated payload"));
+        }
+        let size = usize::try_from(le64(payload, 0))
+            .ok()
+            .filter(|&size| size <= DATA_SIZE_MAX)
+            .ok_or_else(|| invalid_data("lz4: decompressed payload too large"))?;
+        lz4_flex::block::decompress(&payload[8..], size)
+            .map_err(|e| invalid_data(format!("lz4: {}", e)))
+    } else if flags & OBJECT_COMPRESSED_ZSTD != 0 {
+        let mut out = Vec::new();
+        ruzstd::decoding::StreamingDecoder::new(payload)
+            .map_err(|e| invalid_data(format!("zstd: {}", e)))?
+            .take(DATA_SIZE_MAX as u64 + 1)
+            .read_to_end(&mut out)?;
+        if out.len() > DATA_SIZE_MAX {
+            return Err(invalid_data("zstd: decompressed payload too large"));
+        }
+        Ok(out)
+    } else {
+        Ok(payload.to_vec())
+    }
+}
+
+/// Wakes a follower up when journal files change, using inotify where it can
+/// and sleeping out the timeout otherwise.
+struct Watch(Option<Inotify>);
+
+impl Watch {
+    /// Watch files or directories for entries being written and for files
+    /// being created or renamed into place.
+    fn new(paths: &[PathBuf]) -> Self {
+        let inotify = Inotify::init().and_then(|inotify| {
+            for path in paths {
+                inotify.watches().add(
+                    path,
+                    WatchMask::MODIFY | WatchMask::CREATE | WatchMask::MOVED_TO,
+                )?;
+            }
+            Ok(inotify)
+        });
+
+        Self(inotify.ok())
+    }
+
+    /// Wait up to `timeout` for a change.
+    fn wait(&mut self, timeout: Duration) -> io::Result<()> {
+        let inotify = match &mut self.0 {
+            Some(inotify) => inotify,
+            None => {
+                thread::sleep(timeout);
+                return Ok(());
+            }
+        };
+
+        let mut fd = libc::pollfd {
+            fd: inotify.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let timeout = timeout.as_millis().min(libc::c_int::MAX as u128) as libc::c_int;
+        // SAFETY: `fd` is a single valid pollfd for the duration of the call.
+        if unsafe { libc::poll(&mut fd, 1, timeout) } < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(err);
+            }
+        }
+
+        // Only whether anything changed matters, drain the events.
+        let mut buffer = [0u8; 4096];
+        loop {
+            match inotify.read_events(&mut buffer) {
+                Ok(_) => continue,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// A single journal file, read entry by entry in the order of the global
+/// entry array.
+pub struct JournalFile {
+    file: File,
+    path: PathBuf,
+    // Device and inode, which stay the same when journald renames the file.
+    id: (u64, u64),
+    // Whether the file uses the compact object layout.
+    compact: bool,
+    // Number of entries recorded in the header.
+    n_entries: u64,
+    // End of the object arena, objects beyond this are invalid.
+    arena_end: u64,
+    // File length when the header was last read, which a corrupt arena
+    // size or object size must not reach past.
+    file_len: u64,
+    // Offset of the first entry array object.
+    entry_array_offset: u64,
+    // Offset of the entry array being read and of the next one to load.
+    array: u64,
+    next_array: u64,
+    // Entry offsets from the current entry array and the position within it.
+    array_items: Vec<u64>,
+    array_pos: usize,
+    // Number of entries returned so far.
+    seen: u64,
+    // Set up the first time the file is waited on.
+    watch: Option<Watch>,
+}
+
+impl JournalFile {
+    /// Open a journal file and validate its header.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let mut header = vec![0u8; HEADER_MIN_SIZE as usize];
+        file.read_exact_at(&mut header, 0)
+            .map_err(|_| invalid_data(format!("{}: not a journal file", path.display())))?;
+
+        if &header[..8] != SIGNATURE {
+            return Err(invalid_data(format!(
+                "{}: not a journal file",
+                path.display()
+            )));
+        }
+
+        let incompatible = le32(&header, HEADER_INCOMPATIBLE_FLAGS);
+        if incompatible & !HEADER_INCOMPATIBLE_SUPPORTED != 0 {
+            return Err(invalid_data(format!(
+                "{}: unsupported incompatible flags {:#x}",
+                path.display(),
+                incompatible
+            )));
+        }
+
+        let header_size = le64(&header, HEADER_HEADER_SIZE);
+        if header_size < HEADER_MIN_SIZE {
+            return Err(invalid_data(format!(
+                "{}: header too small",
+                path.display()
+            )));
+        }
+
+        let metadata = file.metadata()?;
+
+        Ok(Self {
+            file,
+            path: path.to_path_buf(),
+            id: (metadata.dev(), metadata.ino()),
+            compact: incompatible & HEADER_INCOMPATIBLE_COMPACT != 0,
+            n_entries: le64(&header, HEADER_N_ENTRIES),
+            arena_end: header_size.saturating_add(le64(&header, HEADER_ARENA_SIZE)),
+            file_len: metadata.len(),
+            entry_array_offset: le64(&header, HEADER_ENTRY_ARRAY_OFFSET),
+            array: 0,
+            next_array: le64(&header, HEADER_ENTRY_ARRAY_OFFSET),
+            array_items: Vec::new(),
+            array_pos: 0,
+            seen: 0,
+            watch: None,
+        })
+    }
+
+    /// The path this journal file was opened from.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Pick up entries written since the header was read, for following a
+    /// file journald is still writing to.
+    fn refresh(&mut self) -> io::Result<()> {
+        let mut header = vec![0u8; HEADER_MIN_SIZE as usize];
+        self.file.read_exact_at(&mut header, 0)?;
+        self.n_entries = le64(&header, HEADER_N_ENTRIES);
+        self.arena_end =
+            le64(&header, HEADER_HEADER_SIZE).saturating_add(le64(&header, HEADER_ARENA_SIZE));
+        self.file_len = self.file.metadata()?.len();
+        self.entry_array_offset = le64(&header, HEADER_ENTRY_ARRAY_OFFSET);
+
+        if self.array != 0 {
+            // New entries go into the free slots of the last array, or into
+            // a new array linked from it.
+            let pos = self.array_pos;
+            self.load_entry_array(self.array)?;
+            self.array_pos = pos;
+        } else if self.next_array == 0 {
+            self.next_array = self.entry_array_offset;
+        }
+
+        Ok(())
+    }
+
+    /// Read a whole object at `offset`, checking it is of the expected type.
+    /// Returns the object flags and its bytes, including the object header.
+    fn read_object(&self, offset: u64, object_type: u8) -> io::Result<(u8, Vec<u8>)> {
+        let end = self.arena_end.min(self.file_len);
+        if !offset.is_multiple_of(8) || offset.saturating_add(OBJECT_HEADER_SIZE) > end {
+            return Err(invalid_data(format!(
+                "{}: bad object offset {:#x}",
+                self.path.display(),
+                offset
+            )));
+        }
+
+        let mut header = [0u8; OBJECT_HEADER_SIZE as usize];
+        self.file.read_exact_at(&mut header, offset)?;
+
+        let size = le64(&header, 8);
+        if header[0] != object_type
+            || size < OBJECT_HEADER_SIZE
+            || offset.saturating_add(size) > end
+        {
+            return Err(invalid_data(format!(
+                "{}: bad object at {:#x}",
+                self.path.display(),
+                offset
+            )));
+        }
+
+        let mut object = vec![0u8; size as usize];
+        self.file.read_exact_at(&mut object, offset)?;
+
+        Ok((header[1], object))
+    }
+
+    /// Load the entry offsets of the entry array at `offset`.
+    fn load_entry_array(&mut self, offset: u64) -> io::Result<()> {
+        let (_, object) = self.read_object(offset, OBJECT_ENTRY_ARRAY)?;
+        if object.len() < ENTRY_ARRAY_OBJECT_SIZE {
+            return Err(invalid_data(format!(
+                "{}: truncated entry array at {:#x}",
+                self.path.display(),
+                offset
+            )));
+        }
+
+        self.array = offset;
+        self.next_array = le64(&object, 16);
+
+        let items = &object[ENTRY_ARRAY_OBJECT_SIZE..];
+        self.array_items = if self.compact {
+            items.chunks_exact(4).map(|c| le32(c, 0) as u64).collect()
+        } else {
+            items.chunks_exact(8).map(|c| le64(c, 0)).collect()
+        };
+        self.array_pos = 0;
+
+        Ok(())
+    }
+
+    /// The offset of the next entry object, if any.
+    fn next_entry_offset(&mut self) -> io::Result<Option<u64>> {
+        loop {
+            if self.seen >= self.n_entries {
+                return Ok(None);
+            }
+
+            if let Some(&offset) = self.array_items.get(self.array_pos) {
+                // Unused slots at the end of the last array are zeroed.
+                if offset == 0 {
+                    return Ok(None);
+                }
+                self.array_pos += 1;
+                self.seen += 1;
+                return Ok(Some(offset));
+            }
+
+            if self.next_array == 0 {
+                return Ok(None);
+            }
+            self.load_entry_array(self.next_array)?;
+        }
+    }
+
+    /// Read the payload of the DATA object at `offset` as a field name and value.
+    fn read_data(&self, offset: u64) -> io::Result<Option<(String, String)>> {
+        let (flags, object) = self.read_object(offset, OBJECT_DATA)?;
+        let start = if self.compact {
+            DATA_OBJECT_COMPACT_SIZE
+        } else {
+            DATA_OBJECT_SIZE
+        };
+        if object.len() < start {
+            return Err(invalid_data(format!(
+                "{}: truncated data object at {:#x}",
+                self.path.display(),
+                offset
+            )));
+        }
+
+        let payload = decompress(flags, &object[start..])?;
+        let field = match payload.iter().position(|&b| b == b'=') {
+            Some(eq) => (
+                String::from_utf8_lossy(&payload[..eq]).into(),
+                String::from_utf8_lossy(&payload[eq + 1..]).into(),
+            ),
+            None => return Ok(None),
+        };
+
+        Ok(Some(field))
+    }
+
+    /// Read the ENTRY object at `offset`, returning its realtime timestamp and fields.
+    fn read_entry(&self, offset: u64) -> io::Result<(u64, Entry)> {
+        let (_, object) = self.read_object(offset, OBJECT_ENTRY)?;
+        if object.len() < ENTRY_OBJECT_SIZE {
+            return Err(invalid_data(format!(
+                "{}: truncated entry at {:#x}",
+                self.path.display(),
+                offset
+            )));
+        }
+
+        let realtime = le64(&object, 24);
+        let items = &object[ENTRY_OBJECT_SIZE..];
+        let offsets: Vec<u64> = if self.compact {
+            items.chunks_exact(4).map(|c| le32(c, 0) as u64).collect()
+        } else {
+            // Each item is the data object offset followed by its hash.
+            items.chunks_exact(16).map(|c| le64(c, 0)).collect()
+        };
+
+        let mut entry = Entry::new();
+        for data in offsets {
+            if let Some((name, value)) = self.read_data(data)? {
+                entry.insert(name, value);
+            }
+        }
+        entry.insert(REALTIME_TIMESTAMP.to_string(), realtime.to_string());
+
+        Ok((realtime, entry))
+    }
+
+    /// Read just the realtime timestamp of the ENTRY object at `offset`.
+    fn entry_realtime(&self, offset: u64) -> io::Result<u64> {
+        let mut object = [0u8; 32];
+        self.file.read_exact_at(&mut object, offset)?;
+        if object[0] != OBJECT_ENTRY {
+            return Err(invalid_data(format!(
+                "{}: bad object at {:#x}",
+                self.path.display(),
+                offset
+            )));
+        }
+
+        Ok(le64(&object, 24))
+    }
+
+    /// Read the next entry along with its realtime timestamp.
+    fn next_timestamped(&mut self) -> io::Result<Option<(u64, Entry)>> {
+        match self.next_entry_offset()? {
+            Some(offset) => self.read_entry(offset).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+impl EntrySource for JournalFile {
+    fn next_entry(&mut self) -> io::Result<Option<Entry>> {
+        Ok(self.next_timestamped()?.map(|(_, entry)| entry))
+    }
+
+    fn seek_realtime(&mut self, usec: u64) -> io::Result<()> {
+        self.array = 0;
+        self.next_array = self.entry_array_offset;
+        self.array_items.clear();
+        self.array_pos = 0;
+        self.seen = 0;
+
+        // Entries are in time order, so like sd_journal skip the entry arrays
+        // ending before `usec` and bisect the one that does not, reading
+        // only entry timestamps.
+        while self.next_array != 0 {
+            self.load_entry_array(self.next_array)?;
+            // Unused slots at the end of the last array are zeroed.
+            let used = self
+                .array_items
+                .iter()
+                .position(|&offset| offset == 0)
+                .unwrap_or(self.array_items.len())
+                .min(self.n_entries.saturating_sub(self.seen) as usize);
+            let items = &self.array_items[..used];
+
+            let last = match items.last() {
+                Some(&last) => self.entry_realtime(last)?,
+                None => break,
+            };
+            if last < usec {
+                self.array_pos = used;
+                self.seen += used as u64;
+                continue;
+            }
+
+            let (mut low, mut high) = (0, used);
+            while low < high {
+                let mid = (low + high) / 2;
+                if self.entry_realtime(items[mid])? < usec {
+                    low = mid + 1;
+                } else {
+                    high = mid;
+                }
+            }
+            self.array_pos = low;
+            self.seen += low as u64;
+            break;
+        }
+
+        Ok(())
+    }
+
+    fn wait(&mut self, timeout: Duration) -> io::Result<()> {
+        self.watch
+            .get_or_insert_with(|| Watch::new(std::slice::from_ref(&self.path)))
+            .wait(timeout)?;
+        self.refresh()
+    }
+}
+
+/// All journal files in a directory, interleaved by realtime timestamp.
+pub struct JournalDirectory {
+    path: PathBuf,
+    files: Vec<JournalFile>,
+    // The next entry of each file, ordered by timestamp then file index.
+    heads: BinaryHeap<Reverse<(u64, usize)>>,
+    pending: Vec<Option<Entry>>,
+    // Set up the first time the directory is waited on.
+    watch: Option<Watch>,
+    // Files skipped or stopped reading since the caller last asked.
+    warnings: Vec<String>,
+}
+
+impl JournalDirectory {
+    /// Open every journal file in `path` and its immediate subdirectories,
+    /// which is where journald keeps per machine ID journals.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut dir = Self {
+            path: path.to_path_buf(),
+            files: Vec::new(),
+            heads: BinaryHeap::new(),
+            pending: Vec::new(),
+            watch: None,
+            warnings: Vec::new(),
+        };
+        dir.add_new_files()?;
+
+        Ok(dir)
+    }
+
+    /// Open the journal files not already open, such as those created when
+    /// journald rotates its files.
+    fn add_new_files(&mut self) -> io::Result<()> {
+        for path in source::journal_files(&self.path)? {
+            let known = fs::metadata(&path)
+                .is_ok_and(|m| self.files.iter().any(|file| file.id == (m.dev(), m.ino())));
+            if known {
+                continue;
+            }
+
+            // Like sd_journal, skip files that cannot be read rather than
+            // failing the whole directory.
+            match JournalFile::open(&path) {
+                Ok(file) => {
+                    self.files.push(file);
+                    self.pending.push(None);
+                    let idx = self.files.len() - 1;
+                    if let Err(e) = self.refill(idx) {
+                        self.stopped_reading(idx, e);
+                    }
+                }
+                Err(e) => self
+                    .warnings
+                    .push(format!("skipping {}: {}", path.display(), e)),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Give up on file `idx` after failing to read it, keeping the entries
+    /// already read like sd_journal does.
+    fn stopped_reading(&mut self, idx: usize, e: io::Error) {
+        let path = self.files[idx].path().display();
+        self.warnings
+            .push(format!("stopped reading {}: {}", path, e));
+    }
+
+    /// Read the next entry of file `idx` into the merge heap.
+    fn refill(&mut self, idx: usize) -> io::Result<()> {
+        if let Some((realtime, entry)) = self.files[idx].next_timestamped()? {
+            self.pending[idx] = Some(entry);
+            self.heads.push(Reverse((realtime, idx)));
+        }
+
+        Ok(())
+    }
+}
+
+impl EntrySource for JournalDirectory {
+    fn next_entry(&mut self) -> io::Result<Option<Entry>> {
+        let idx = match self.heads.pop() {
+            Some(Reverse((_, idx))) => idx,
+            None => return Ok(None),
+        };
+        let entry = self.pending[idx].take();
+
+        if let Err(e) = self.refill(idx) {
+            self.stopped_reading(idx, e);
+        }
+
+        Ok(entry)
+    }
+
+    fn seek_realtime(&mut self, usec: u64) -> io::Result<()> {
+        self.heads.clear();
+        for idx in 0..self.files.len() {
+            self.pending[idx] = None;
+            if let Err(e) = self.files[idx]
+                .seek_realtime(usec)
+                .and_then(|_| self.refill(idx))
+            {
+                self.stopped_reading(idx, e);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn wait(&mut self, timeout: Duration) -> io::Result<()> {
+        if self.watch.is_none() {
+            // journald creates files in the machine ID subdirectories.
+            let mut dirs = vec![self.path.clone()];
+            for dirent in fs::read_dir(&self.path)? {
+                let dirent = dirent?;
+                if dirent.file_type()?.is_dir() {
+                    dirs.push(dirent.path());
+                }
+            }
+            self.watch = Some(Watch::new(&dirs));
+        }
+        self.watch.as_mut().unwrap().wait(timeout)?;
+
+        for idx in 0..self.files.len() {
+            // Files with an entry pending have not been read to the end.
+            if self.pending[idx].is_some() {
+                continue;
+            }
+            if let Err(e) = self.files[idx].refresh().and_then(|_| self.refill(idx)) {
+                self.stopped_reading(idx, e);
+            }
+        }
+
+        self.add_new_files()
+    }
+
+    fn take_warnings(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.warnings)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Minimal journal file writer covering what the reader needs.
+    struct Writer {
+        buf: Vec<u8>,
+        compact: bool,
+        entries: Vec<u64>,
+        seqnum: u64,
+    }
+
+    impl Writer {
+        const HEADER_SIZE: usize = 272;
+
+        fn new(compact: bool) -> Self {
+            let mut w = Self {
+                buf: vec![0u8; Self::HEADER_SIZE],
+                compact,
+                entries: Vec::new(),
+                seqnum: 0,
+            };
+            // Empty hash tables, never consulted for a sequential read.
+            let data_table = w.object(4, 0, &[0u8; 16 * 4]) + OBJECT_HEADER_SIZE;
+            let field_table = w.object(5, 0, &[0u8; 16 * 4]) + OBJECT_HEADER_SIZE;
+            w.put64(104, data_table);
+            w.put64(112, 16 * 4);
+            w.put64(120, field_table);
+            w.put64(128, 16 * 4);
+            w
+        }
+
+        fn put64(&mut self, offset: usize, value: u64) {
+            self.buf[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+        }
+
+        fn object(&mut self, object_type: u8, flags: u8, body: &[u8]) -> u64 {
+            while !self.buf.len().is_multiple_of(8) {
+                self.buf.push(0);
+            }
+            let offset = self.buf.len() as u64;
+            self.buf
+                .extend_from_slice(&[object_type, flags, 0, 0, 0, 0, 0, 0]);
+            self.buf
+                .extend_from_slice(&(OBJECT_HEADER_SIZE + body.len() as u64).to_le_bytes());
+            self.buf.extend_from_slice(body);
+            offset
+        }
+
+        fn data(&mut self, payload: &[u8], flags: u8) -> u64 {
+            let mut body = vec![0u8; if self.compact { 56 } else { 48 }];
+            body.extend_from_slice(&match flags {
+                OBJECT_COMPRESSED_XZ => {
+                    let mut out = Vec::new();
+                    lzma_rs::xz_compress(&mut &payload[..], &mut out).unwrap();
+                    out
+                }
+                OBJECT_COMPRESSED_LZ4 => {
+                    let mut out = (payload.len() as u64).to_le_bytes().to_vec();
+                    out.extend(lz4_flex::block::compress(payload));
+                    out
+                }
+                OBJECT_COMPRESSED_ZSTD => ruzstd::encoding::compress_to_vec(
+                    payload,
+                    ruzstd::encoding::CompressionLevel::Fastest,
+                ),
+                _ => payload.to_vec(),
+            });
+            self.object(OBJECT_DATA, flags, &body)
+        }
+
+        fn entry(&mut self, realtime: u64, fields: &[(&str, u8)]) {
+            let offsets: Vec<u64> = fields
+                .iter()
+                .map(|(p, f)| self.data(p.as_bytes(), *f))
+                .collect();
+            self.seqnum += 1;
+            let mut body = Vec::new();
+            body.extend_from_slice(&self.seqnum.to_le_bytes());
+            body.extend_from_slice(&realtime.to_le_bytes());
+            body.extend_from_slice(&[0u8; 32]);
+            for offset in offsets {
+                if self.compact {
+                    body.extend_from_slice(&(offset as u32).to_le_bytes());
+                } else {
+                    body.extend_from_slice(&offset.to_le_bytes());
+                    body.extend_from_slice(&0u64.to_le_bytes());
+                }
+            }
+            let offset = self.object(OBJECT_ENTRY, 0, &body);
+            self.entries.push(offset);
+        }
+
+        /// Lay the entries out over two chained arrays, the last with an
+        /// unused slot, and fill in the header.
+        fn finish(mut self) -> Vec<u8> {
+            let item = |w: &Self, offset: u64| {
+                if w.compact {
+                    (offset as u32).to_le_bytes().to_vec()
+                } else {
+                    offset.to_le_bytes().to_vec()
+                }
+            };
+            let split = self.entries.len() / 2;
+            let mut first = 0u64.to_le_bytes().to_vec();
+            for &e in &self.entries[..split] {
+                first.extend(item(&self, e));
+            }
+            let first = self.object(OBJECT_ENTRY_ARRAY, 0, &first);
+            let mut second = 0u64.to_le_bytes().to_vec();
+            for &e in &self.entries[split..] {
+                second.extend(item(&self, e));
+            }
+            second.extend(item(&self, 0));
+            let second = self.object(OBJECT_ENTRY_ARRAY, 0, &second);
+            self.put64(first as usize + 16, second);
+
+            self.buf[..8].copy_from_slice(SIGNATURE);
+            let flags = if self.compact {
+                HEADER_INCOMPATIBLE_COMPACT
+            } else {
+                0
+            } | HEADER_INCOMPATIBLE_COMPRESSED_XZ
+                | HEADER_INCOMPATIBLE_COMPRESSED_LZ4
+                | HEADER_INCOMPATIBLE_COMPRESSED_ZSTD;
+            self.buf[12..16].copy_from_slice(&flags.to_le_bytes());
+            self.put64(88, Self::HEADER_SIZE as u64);
+            self.put64(96, (self.buf.len() - Self::HEADER_SIZE) as u64);
+            self.put64(152, self.entries.len() as u64);
+            self.put64(176, first);
+            self.buf
+        }
+    }
+
+    /// A journal file of `FIELD=value` payloads, each entry with its
+    /// realtime timestamp.
+    pub(crate) fn journal_file(entries: &[(u64, Vec<String>)]) -> Vec<u8> {
+        let mut w = Writer::new(false);
+        for (realtime, fields) in entries {
+            let fields: Vec<(&str, u8)> = fields.iter().map(|f| (f.as_str(), 0)).collect();
+            w.entry(*realtime, &fields);
+        }
+        w.finish()
+    }
+
+    fn write(dir: &Path, name: &str, data: Vec<u8>) -> PathBuf {
+        fs::create_dir_all(dir).unwrap();
+        let path = dir.join(name);
+        fs::write(&path, data).unwrap();
+        path
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "journalstat-native-{}-{}",
+            name,
+            std::process::id()
+        ))
+    }
+
+    fn read_all(source: &mut dyn EntrySource) -> Vec<Entry> {
+        let mut entries = Vec::new();
+        while let Some(entry) = source.next_entry().unwrap() {
+            entries.push(entry);
+        }
+        entries
+    }
+
+    fn sample(compact: bool) -> Vec<u8> {
+        let mut w = Writer::new(compact);
+        w.entry(
+            1,
+            &[
+                ("MESSAGE=plain", 0),
+                ("_COMM=sshd", OBJECT_COMPRESSED_LZ4),
+                ("PRIORITY=6", 0),
+            ],
+        );
+        w.entry(
+            2,
+            &[
+                ("MESSAGE=xz compressed", OBJECT_COMPRESSED_XZ),
+                ("_COMM=cron", 0),
+                ("PRIORITY=3", OBJECT_COMPRESSED_ZSTD),
+            ],
+        );
+        w.entry(3, &[("MESSAGE=a=b", 0), ("NOEQUALS", 0)]);
+        w.finish()
+    }
+
+    #[test]
+    fn reads_entries_and_compressed_data() {
+        for compact in [false, true] {
+            let path = write(
+                &temp_dir("file"),
+                &format!("c{}.journal", compact),
+                sample(compact),
+            );
+            let entries = read_all(&mut JournalFile::open(&path).unwrap());
+
+            assert_eq!(entries.len(), 3);
+            assert_eq!(entries[0]["MESSAGE"], "plain");
+            assert_eq!(entries[0]["_COMM"], "sshd");
+            assert_eq!(entries[0][REALTIME_TIMESTAMP], "1");
+            assert_eq!(entries[1]["MESSAGE"], "xz compressed");
+            assert_eq!(entries[1]["PRIORITY"], "3");
+            // Only the first '=' separates the field name from its value.
+            assert_eq!(entries[2]["MESSAGE"], "a=b");
+            assert_eq!(entries[2].len(), 2);
+        }
+    }
+
+    #[test]
+    fn seeks_by_bisecting_entry_arrays() {
+        let entries: Vec<(u64, Vec<String>)> = (1..=9)
+            .map(|n| (n * 10, vec![format!("MESSAGE=m{}", n * 10)]))
+            .collect();
+        let path = write(&temp_dir("seek"), "seek.journal", journal_file(&entries));
+        let mut file = JournalFile::open(&path).unwrap();
+
+        // Both sides of the split between the two entry arrays, and past
+        // the end.
+        for (usec, first, left) in [
+            (0, Some("m10"), 9),
+            (35, Some("m40"), 6),
+            (50, Some("m50"), 5),
+            (90, Some("m90"), 1),
+            (91, None, 0),
+        ] {
+            file.seek_realtime(usec).unwrap();
+            let entries = read_all(&mut file);
+            assert_eq!(entries.first().map(|e| e["MESSAGE"].as_str()), first);
+            assert_eq!(entries.len(), left);
+        }
+    }
+
+    #[test]
+    fn rejects_objects_past_the_end_of_the_file() {
+        let mut data = sample(false);
+        // A huge arena and first DATA object, after the two hash tables.
+        data[96..104].copy_from_slice(&(1u64 << 50).to_le_bytes());
+        let first_data = Writer::HEADER_SIZE + 2 * (16 + 64);
+        data[first_data + 8..first_data + 16].copy_from_slice(&(1u64 << 40).to_le_bytes());
+        let path = write(&temp_dir("huge"), "huge.journal", data);
+
+        let err = JournalFile::open(&path).unwrap().next_entry().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn rejects_forged_decompressed_sizes() {
+        let mut payload = u64::MAX.to_le_bytes().to_vec();
+        payload.extend_from_slice(b"\x10x");
+
+        let err = decompress(OBJECT_COMPRESSED_LZ4, &payload).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn rejects_non_journal_files() {
+        let path = write(&temp_dir("bad"), "bad.journal", b"not a journal".to_vec());
+        assert!(JournalFile::open(&path).is_err());
+    }
+
+    #[test]
+    fn directory_interleaves_by_timestamp() {
+        let dir = temp_dir("dir");
+        let mut a = Writer::new(false);
+        a.entry(10, &[("MESSAGE=a10", 0)]);
+        a.entry(30, &[("MESSAGE=a30", 0)]);
+        write(&dir, "system.journal", a.finish());
+
+        let mut b = Writer::new(true);
+        b.entry(20, &[("MESSAGE=b20", 0)]);
+        b.entry(40, &[("MESSAGE=b40", 0)]);
+        write(&dir.join("machine-id"), "user-1000.journal~", b.finish());
+
+        write(&dir, "ignored.txt", b"not a journal".to_vec());
+        write(&dir, "broken.journal", b"not a journal".to_vec());
+
+        let messages = |source: &mut dyn EntrySource| -> Vec<String> {
+            read_all(source)
+                .into_iter()
+                .map(|e| e["MESSAGE"].clone())
+                .collect()
+        };
+        let mut journal = JournalDirectory::open(&dir).unwrap();
+        assert_eq!(messages(&mut journal), ["a10", "b20", "a30", "b40"]);
+        let warnings = journal.take_warnings();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("skipping "), "{}", warnings[0]);
+        assert!(journal.take_warnings().is_empty());
+
+        journal.seek_realtime(25).unwrap();
+        assert_eq!(messages(&mut journal), ["a30", "b40"]);
+
+        // Following picks up the file journald rotates to, and not the
+        // files already read under a new name.
+        fs::rename(dir.join("system.journal"), dir.join("system@1.journal")).unwrap();
+        let mut c = Writer::new(false);
+        c.entry(50, &[("MESSAGE=c50", 0)]);
+        write(&dir, "system.journal", c.finish());
+
+        journal.wait(Duration::ZERO).unwrap();
+        assert_eq!(messages(&mut journal), ["c50"]);
+    }
+}
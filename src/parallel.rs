@@ -0,0 +1,307 @@
+//! Reading the files of a journal directory on several threads at once.
+//!
+//! Every thread counts the files it takes into statistics of its own, merged
+//! once all files are read. The result is that of reading the directory on
+//! one thread, except for template samples, the first message seen for a
+//! template, and approximate counts, which stay within their error bounds.
+//! Bursts, Drain templates, other analyzers that cannot be merged, sliding
+//! windows and exported metrics depend on the order of entries across files
+//! and are only gathered on one thread.
+
+use crate::{
+    analyzer::Analyzer,
+    fallback::Fallbacks,
+    filter::MatchExpr,
+    priority::PriorityRange,
+    source::{self, Backend, Entry, EntrySource, InputFormat},
+    JournalStat, Result,
+};
+use regex::Regex;
+use std::{
+    io,
+    num::NonZeroUsize,
+    panic,
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+/// A source without entries, until a worker opens its first file.
+struct Empty;
+
+impl EntrySource for Empty {
+    fn next_entry(&mut self) -> io::Result<Option<Entry>> {
+        Ok(None)
+    }
+}
+
+/// What a worker needs to count entries like the JournalStat it works for.
+struct Settings {
+    input: PathBuf,
+    unit: Option<String>,
+    regex: Option<Regex>,
+    matches: Option<MatchExpr>,
+    priority: Option<PriorityRange>,
+    boot: Option<String>,
+    since: Option<u64>,
+    until: Option<u64>,
+    // Forks of the statistics gathered, and how many are built in.
+    analyzers: Vec<Box<dyn Analyzer>>,
+    builtins: usize,
+}
+
+/// The statistics a worker counted.
+struct Counts {
+    total_msgs: u64,
+    fallbacks: Fallbacks,
+    first_seen: Option<u64>,
+    last_seen: Option<u64>,
+    analyzers: Vec<Box<dyn Analyzer>>,
+    // Files skipped or stopped reading.
+    warnings: Vec<String>,
+}
+
+impl Settings {
+    /// Set up a JournalStat counting with these settings.
+    fn worker(self) -> JournalStat {
+        let mut stat = JournalStat::new(&self.input, Box::new(Empty));
+        stat.set_filter_unit(&self.unit)
+            .set_regex(&self.regex)
+            .set_filter_matches(self.matches)
+            .set_filter_priority(self.priority)
+            .set_filter_boot(&self.boot)
+            .set_time_range(self.since, self.until);
+        stat.analyzers = self.analyzers;
+        stat.builtins = self.builtins;
+        stat
+    }
+}
+
+impl JournalStat {
+    /// Whether the statistics asked for can be counted per file and merged.
+    fn mergeable(&self) -> bool {
+        self.metrics.is_none()
+            && self.window.is_none()
+            && self.analyzers.iter().all(|a| a.mergeable())
+    }
+
+    fn settings(&self) -> Settings {
+        Settings {
+            input: self.input.clone(),
+            unit: self.unit.clone(),
+            regex: self.regex.clone(),
+            matches: self.matches.clone(),
+            priority: self.priority,
+            boot: self.boot.clone(),
+            since: self.since,
+            until: self.until,
+            analyzers: self.analyzers.iter().map(|a| a.fork()).collect(),
+            builtins: self.builtins,
+        }
+    }
+
+    fn into_counts(self) -> Counts {
+        Counts {
+            total_msgs: self.total_msgs,
+            fallbacks: self.fallbacks,
+            first_seen: self.first_seen,
+            last_seen: self.last_seen,
+            analyzers: self.analyzers,
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Add the statistics a worker counted.
+    fn merge(&mut self, counts: Counts) -> Result<()> {
+        for warning in &counts.warnings {
+            self.warn(warning);
+        }
+        self.total_msgs += counts.total_msgs;
+        self.fallbacks.merge(counts.fallbacks);
+        self.first_seen = self.first_seen.into_iter().chain(counts.first_seen).min();
+        self.last_seen = self.last_seen.into_iter().chain(counts.last_seen).max();
+
+        for (mine, theirs) in self.analyzers.iter_mut().zip(counts.analyzers) {
+            mine.merge(theirs)?;
+        }
+        Ok(())
+    }
+
+    /// Read the journal files of the input directory on `jobs` threads, one
+    /// per CPU for 0, opening them with `backend`. Any other input, or
+    /// statistics that depend on the order of entries across files, are
+    /// read on one thread as with [`JournalStat::parse`]. As there, files
+    /// that cannot be read are skipped with a warning, keeping the entries
+    /// read before an error.
+    pub fn parse_parallel(&mut self, backend: Backend, jobs: usize) -> Result<&mut Self> {
+        if !self.input.is_dir() || !self.mergeable() {
+            return self.parse();
+        }
+
+        let files = source::journal_files(&self.input)?;
+        let jobs = match jobs {
+            0 => thread::available_parallelism().map_or(1, NonZeroUsize::get),
+            jobs => jobs,
+        }
+        .min(files.len())
+        .max(1);
+
+        // Workers take the next file not yet taken until none are left.
+        let next = AtomicUsize::new(0);
+        let workers: Vec<Settings> = (0..jobs).map(|_| self.settings()).collect();
+
+        let counts = thread::scope(|scope| {
+            let handles: Vec<_> = workers
+                .into_iter()
+                .map(|settings| {
+                    let (files, next) = (&files, &next);
+                    scope.spawn(move || -> Counts {
+                        let mut stat = settings.worker();
+                        let mut warnings = Vec::new();
+                        while let Some(path) = files.get(next.fetch_add(1, Ordering::Relaxed)) {
+                            stat.journal = match source::open(path, InputFormat::Journal, backend) {
+                                Ok(journal) => journal,
+                                Err(e) => {
+                                    warnings.push(format!("skipping {}: {}", path.display(), e));
+                                    continue;
+                                }
+                            };
+                            stat.start();
+                            loop {
+                                match stat.journal.next_entry() {
+                                    Ok(Some(entry)) if stat.take(&entry) => {}
+                                    Ok(_) => break,
+                                    Err(e) => {
+                                        warnings.push(format!(
+                                            "stopped reading {}: {}",
+                                            path.display(),
+                                            e
+                                        ));
+                                        break;
+                                    }
+                                }
+                            }
+                        }
+                        let mut counts = stat.into_counts();
+                        counts.warnings = warnings;
+                        counts
+                    })
+                })
+                .collect();
+
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap_or_else(|e| panic::resume_unwind(e)))
+                .collect::<Vec<Counts>>()
+        });
+
+        for counts in counts {
+            self.merge(counts)?;
+        }
+        self.complete();
+        Ok(self)
+    }
+}
+
+#[cfg(all(test, feature = "native"))]
+mod tests {
+    use super::*;
+    use crate::{histogram::Interval, native::tests::journal_file, template::ClusterMode};
+    use std::{cell::RefCell, fs, rc::Rc};
+
+    fn entry(msg: &str, process: &str, ts: u64) -> (u64, Vec<String>) {
+        let fields = vec![
+            format!("MESSAGE={}", msg),
+            format!("_COMM={}", process),
+            "PRIORITY=6".to_string(),
+            format!("_SYSTEMD_UNIT={}.service", process),
+        ];
+        (ts, fields)
+    }
+
+    #[test]
+    fn parallel_matches_sequential() {
+        let dir = std::env::temp_dir().join(format!("journalstat-parallel-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("machine")).unwrap();
+        for (i, name) in ["a.journal", "machine/b.journal", "machine/c.journal~"]
+            .iter()
+            .enumerate()
+        {
+            let entries: Vec<_> = (0..20u64)
+                .map(|n| {
+                    let process = ["app", "cron", "sshd"][(n as usize + i) % 3];
+                    entry(&format!("job {} done", n % 4), process, n * 3 + i as u64)
+                })
+                .collect();
+            fs::write(dir.join(name), journal_file(&entries)).unwrap();
+        }
+
+        let report = |jobs: Option<usize>| {
+            let mut stat = JournalStat::open(&dir, InputFormat::Auto, Backend::Native).unwrap();
+            stat.n_frequent(5)
+                .n_largest(2)
+                .cluster(Some(ClusterMode::Mask))
+                .footprint(true)
+                .inventory(true)
+                .cardinality(Some(vec!["MESSAGE".to_string(), "_COMM".to_string()]))
+                .per_boot(true)
+                .histogram(Some(Interval(10)))
+                .set_time_range(Some(5), Some(50))
+                .analyzer(Box::new(crate::analyzer::FieldCounts::new(
+                    "units",
+                    "_SYSTEMD_UNIT",
+                    5,
+                )));
+            match jobs {
+                Some(jobs) => stat.parse_parallel(Backend::Native, jobs).unwrap(),
+                None => stat.parse().unwrap(),
+            };
+            let mut json = serde_json::to_value(stat.report_document()).unwrap();
+            // Samples are the first message seen for a template.
+            for template in json["templates"].as_array_mut().unwrap() {
+                template["sample"].take();
+            }
+            json
+        };
+
+        let sequential = report(None);
+        assert_eq!(sequential["totals"]["messages"], 46);
+        assert_eq!(report(Some(2)), sequential);
+        assert_eq!(report(Some(0)), sequential);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn unreadable_files_are_skipped() {
+        let dir = std::env::temp_dir().join(format!("journalstat-skipped-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let entries: Vec<_> = (0..10).map(|n| entry("tick", "cron", n)).collect();
+        let good = journal_file(&entries);
+        fs::write(dir.join("good.journal"), &good).unwrap();
+        fs::write(dir.join("truncated.journal"), &good[..good.len() / 2]).unwrap();
+        fs::write(dir.join("corrupt.journal"), b"not a journal").unwrap();
+
+        let report = |jobs: Option<usize>| {
+            let warnings = Rc::new(RefCell::new(Vec::new()));
+            let seen = warnings.clone();
+            let mut stat = JournalStat::open(&dir, InputFormat::Auto, Backend::Native).unwrap();
+            stat.n_frequent(5)
+                .on_warning(Box::new(move |w| seen.borrow_mut().push(w.to_string())));
+            match jobs {
+                Some(jobs) => stat.parse_parallel(Backend::Native, jobs).unwrap(),
+                None => stat.parse().unwrap(),
+            };
+            let json = serde_json::to_value(stat.report_document()).unwrap();
+            let warnings = warnings.borrow().len();
+            (json, warnings)
+        };
+
+        let (sequential, warnings) = report(None);
+        assert_eq!(sequential["totals"]["messages"], 10);
+        assert_eq!(warnings, 2);
+        assert_eq!(report(Some(2)), (sequential, warnings));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,117 @@
+//! Syslog priority levels and ranges of them, as taken by `--priority`.
+
+use std::str::FromStr;
+
+/// Names of the syslog priorities, indexed by level.
+const NAMES: [&str; 8] = [
+    "emerg", "alert", "crit", "err", "warning", "notice", "info", "debug",
+];
+
+/// The name of a `PRIORITY` field value, if it is a valid level.
+pub fn name(priority: &str) -> Option<&'static str> {
+    priority
+        .parse::<usize>()
+        .ok()
+        .and_then(|level| NAMES.get(level).copied())
+}
+
+/// Parse a single priority given by number or name.
+fn parse_level(s: &str) -> Result<u8, String> {
+    if let Ok(level) = s.parse::<u8>() {
+        if (level as usize) < NAMES.len() {
+            return Ok(level);
+        }
+    }
+
+    let level = match s.to_ascii_lowercase().as_str() {
+        "emerg" | "emergency" | "panic" => 0,
+        "alert" => 1,
+        "crit" | "critical" => 2,
+        "err" | "error" => 3,
+        "warning" | "warn" => 4,
+        "notice" => 5,
+        "info" => 6,
+        "debug" => 7,
+        _ => {
+            return Err(format!(
+                "invalid priority '{}', expected 0-7 or one of {}",
+                s,
+                NAMES.join(", ")
+            ))
+        }
+    };
+
+    Ok(level)
+}
+
+/// An inclusive range of priority levels.
+///
+/// Like journalctl, a single level selects that level and everything more
+/// important, while "FROM..TO" selects exactly the levels between the two.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PriorityRange {
+    /// Most important level, numerically the lowest.
+    pub min: u8,
+    /// Least important level, numerically the highest.
+    pub max: u8,
+}
+
+impl PriorityRange {
+    /// Whether a `PRIORITY` field value falls within the range.
+    pub fn contains(&self, priority: &str) -> bool {
+        priority
+            .parse::<u8>()
+            .is_ok_and(|level| (self.min..=self.max).contains(&level))
+    }
+}
+
+impl FromStr for PriorityRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once("..") {
+            Some((from, to)) => {
+                let (from, to) = (parse_level(from)?, parse_level(to)?);
+                Ok(PriorityRange {
+                    min: from.min(to),
+                    max: from.max(to),
+                })
+            }
+            None => Ok(PriorityRange {
+                min: 0,
+                max: parse_level(s)?,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(s: &str) -> (u8, u8) {
+        let r: PriorityRange = s.parse().unwrap();
+        (r.min, r.max)
+    }
+
+    #[test]
+    fn parses_levels_and_ranges() {
+        assert_eq!(range("err"), (0, 3));
+        assert_eq!(range("4"), (0, 4));
+        assert_eq!(range("warning..emerg"), (0, 4));
+        assert_eq!(range("debug..notice"), (5, 7));
+        assert_eq!(range("2..Error"), (2, 3));
+        assert!("8".parse::<PriorityRange>().is_err());
+        assert!("loud".parse::<PriorityRange>().is_err());
+    }
+
+    #[test]
+    fn matches_priority_fields() {
+        let r: PriorityRange = "crit..warning".parse().unwrap();
+        assert!(r.contains("2"));
+        assert!(r.contains("4"));
+        assert!(!r.contains("1"));
+        assert!(!r.contains("6"));
+        assert!(!r.contains("bogus"));
+    }
+}
@@ -0,0 +1,550 @@
+//! Report output formats and the structured report document.
+//!
+//! The document serialized by `--format json` is described in the README.
+//! Fields are only ever added to it, an incompatible change bumps
+//! `SCHEMA_VERSION`. The CSV and Markdown formats render the same tables as
+//! the default output, one section per table.
+
+use crate::{
+    analyzer::Section,
+    cardinality::{Cardinality, Distinct},
+    fallback::Fallbacks,
+    footprint,
+};
+use serde::Serialize;
+use serde_json::Value;
+use std::{borrow::Cow, collections::HashMap, str::FromStr};
+use tabled::{builder::Builder, Table, Tabled};
+
+/// Version of the JSON report schema.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// How the report is written.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Human readable tables.
+    #[default]
+    Table,
+    /// A single JSON document.
+    Json,
+    /// Comma separated values, one section per table.
+    Csv,
+    /// Markdown tables under a heading each.
+    Markdown,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "table" => Ok(OutputFormat::Table),
+            "json" => Ok(OutputFormat::Json),
+            "csv" => Ok(OutputFormat::Csv),
+            "markdown" | "md" => Ok(OutputFormat::Markdown),
+            _ => Err(format!(
+                "unknown output format '{}', expected 'table', 'json', 'csv' or 'markdown'",
+                s
+            )),
+        }
+    }
+}
+
+/// Quote a CSV field if it holds a separator, quote or line break (RFC 4180).
+fn csv_field(field: &str) -> Cow<'_, str> {
+    if field.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+/// Escape a Markdown table cell, pipes would end the cell, line breaks the
+/// row and angle brackets such as those of `<NUM>` would be taken for HTML.
+fn markdown_cell(cell: &str) -> String {
+    cell.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('\\', "\\\\")
+        .replace('|', "\\|")
+        .replace("\r\n", "<br>")
+        .replace(['\n', '\r'], "<br>")
+}
+
+fn csv_row<'a>(fields: impl IntoIterator<Item = Cow<'a, str>>) -> String {
+    fields
+        .into_iter()
+        .map(|f| csv_field(&f).into_owned())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn markdown_row<'a>(cells: impl IntoIterator<Item = Cow<'a, str>>) -> String {
+    let cells: Vec<String> = cells.into_iter().map(|c| markdown_cell(&c)).collect();
+    format!("| {} |", cells.join(" | "))
+}
+
+/// Render a titled table in one of the tabular formats.
+pub fn table<T: Tabled>(format: OutputFormat, title: &str, rows: Vec<T>) -> String {
+    match format {
+        OutputFormat::Table | OutputFormat::Json => format!("{}\n{}", title, Table::new(rows)),
+        _ => text_table(
+            format,
+            title,
+            T::headers(),
+            rows.iter().map(|row| row.fields()).collect(),
+        ),
+    }
+}
+
+/// Render an analyzer's section in one of the tabular formats.
+pub fn section(format: OutputFormat, section: &Section) -> String {
+    let cell = |value: &Value| match value {
+        Value::String(s) => s.clone(),
+        Value::Null => "-".to_string(),
+        value => value.to_string(),
+    };
+
+    text_table(
+        format,
+        &section.title,
+        section
+            .columns
+            .iter()
+            .map(|c| Cow::Borrowed(c.as_str()))
+            .collect(),
+        section
+            .rows
+            .iter()
+            .map(|row| row.iter().map(|v| Cow::Owned(cell(v))).collect())
+            .collect(),
+    )
+}
+
+/// Render a titled table of text cells.
+pub(crate) fn text_table(
+    format: OutputFormat,
+    title: &str,
+    headers: Vec<Cow<'_, str>>,
+    rows: Vec<Vec<Cow<'_, str>>>,
+) -> String {
+    match format {
+        OutputFormat::Table | OutputFormat::Json => {
+            let mut builder = Builder::default();
+            builder.set_columns(headers);
+            for row in rows {
+                builder.add_record(row);
+            }
+            format!("{}\n{}", title, builder.build())
+        }
+        OutputFormat::Csv => {
+            let title = title.trim_end_matches(':');
+            let mut out = vec![csv_field(title).into_owned(), csv_row(headers)];
+            out.extend(rows.into_iter().map(csv_row));
+            // A blank line ends the section.
+            out.push(String::new());
+            out.join("\n")
+        }
+        OutputFormat::Markdown => {
+            let title = title.trim_end_matches(':');
+            let columns = headers.len();
+            let mut out = vec![
+                format!("## {}\n", markdown_cell(title)),
+                markdown_row(headers),
+                format!("|{}", "---|".repeat(columns)),
+            ];
+            out.extend(rows.into_iter().map(markdown_row));
+            out.push(String::new());
+            out.join("\n")
+        }
+    }
+}
+
+/// The whole report.
+#[derive(Debug, Serialize)]
+pub struct Report<'a> {
+    pub schema_version: u32,
+    pub input: Cow<'a, str>,
+    pub filters: Filters<'a>,
+    pub time_range: TimeRange,
+    pub totals: Totals,
+    /// Entries counted with a fallback for a missing field, or skipped.
+    pub fallbacks: &'a Fallbacks,
+    pub per_process: Vec<ProcessShare<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub footprint: Option<FootprintReport<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inventory: Option<InventoryReport<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cardinality: Option<CardinalityReport<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub histogram: Option<HistogramReport<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bursts: Option<Vec<BurstReport<'a>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub approximate: Option<Approximation>,
+    pub top_talkers: Vec<TopTalker<'a>>,
+    pub largest: Vec<LargeMessage<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub templates: Option<Vec<TemplateCount<'a>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub per_boot: Option<Vec<BootShare<'a>>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub sections: Vec<Section>,
+}
+
+/// Bounds on the message counts when counting approximately.
+#[derive(Debug, Serialize)]
+pub struct Approximation {
+    /// Number of distinct messages tracked at most.
+    pub counters: usize,
+    /// Most any message count may be over.
+    pub max_error: u64,
+}
+
+/// The filters applied while parsing, null when not set.
+#[derive(Debug, Serialize)]
+pub struct Filters<'a> {
+    pub unit: Option<&'a str>,
+    pub pattern: Option<&'a str>,
+    pub matches: &'a [String],
+    pub priority: Option<PriorityFilter>,
+    pub boot: Option<&'a str>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PriorityFilter {
+    pub min: u8,
+    pub max: u8,
+}
+
+/// Timestamps in microseconds since the epoch.
+#[derive(Debug, Serialize)]
+pub struct TimeRange {
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+    pub first_entry: Option<u64>,
+    pub last_entry: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Totals {
+    pub messages: u64,
+    pub processes: usize,
+    /// Unknown when counting approximately, only some being tracked.
+    pub distinct_messages: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProcessShare<'a> {
+    pub rank: usize,
+    pub process: &'a str,
+    pub messages: u32,
+    pub percent: f64,
+}
+
+/// Payload bytes per process, unit and field name.
+#[derive(Debug, Serialize)]
+pub struct FootprintReport<'a> {
+    pub total_bytes: u64,
+    pub per_process: Vec<ByteShare<'a>>,
+    pub per_unit: Vec<ByteShare<'a>>,
+    pub per_field: Vec<ByteShare<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ByteShare<'a> {
+    pub rank: usize,
+    pub name: &'a str,
+    pub bytes: u64,
+    pub percent: f64,
+}
+
+/// Every field seen, carried by the most entries first.
+#[derive(Debug, Serialize)]
+pub struct InventoryReport<'a> {
+    pub entries: u64,
+    pub fields: Vec<FieldReport<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FieldReport<'a> {
+    pub rank: usize,
+    pub field: &'a str,
+    /// Entries carrying the field, and their percentage of all entries.
+    pub entries: u64,
+    pub percent: f64,
+    pub bytes: u64,
+    /// The processes writing the field in the most entries.
+    pub processes: Vec<FieldWriter<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FieldWriter<'a> {
+    pub process: &'a str,
+    pub entries: u64,
+}
+
+/// Distinct values per field, overall and per process.
+#[derive(Debug, Serialize)]
+pub struct CardinalityReport<'a> {
+    pub overall: Vec<FieldCardinality<'a>>,
+    pub per_process: Vec<ProcessCardinality<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProcessCardinality<'a> {
+    pub process: &'a str,
+    pub fields: Vec<FieldCardinality<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FieldCardinality<'a> {
+    pub field: &'a str,
+    /// The number of distinct values, estimated unless `exact`.
+    pub distinct: u64,
+    pub exact: bool,
+}
+
+/// Messages per interval, `counts` matching `starts` index by index.
+#[derive(Debug, Serialize)]
+pub struct HistogramReport<'a> {
+    pub interval_usec: u64,
+    pub starts: Vec<u64>,
+    pub counts: Vec<u64>,
+    pub per_process: Vec<ProcessSeries<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProcessSeries<'a> {
+    pub process: &'a str,
+    pub messages: u64,
+    pub counts: Vec<u64>,
+}
+
+/// A period of unusually high logging, `peak` and `baseline` in messages per
+/// window.
+#[derive(Debug, Serialize)]
+pub struct BurstReport<'a> {
+    pub process: &'a str,
+    pub template: Option<&'a str>,
+    pub start: u64,
+    pub end: u64,
+    pub window_usec: u64,
+    pub peak: u64,
+    pub messages: u64,
+    pub baseline: Option<f64>,
+    pub message: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TopTalker<'a> {
+    pub rank: usize,
+    pub count: u32,
+    pub process: &'a str,
+    pub priority: &'a str,
+    pub priority_name: &'a str,
+    pub message: &'a str,
+    /// Most the count may be over, when counting approximately.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LargeMessage<'a> {
+    pub rank: usize,
+    pub size: usize,
+    pub message: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TemplateCount<'a> {
+    pub rank: usize,
+    pub count: u32,
+    pub variants: usize,
+    pub process: &'a str,
+    pub template: &'a str,
+    pub sample: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BootShare<'a> {
+    pub boot_id: &'a str,
+    pub first_entry: Option<u64>,
+    pub last_entry: Option<u64>,
+    pub messages: u64,
+    pub per_process: Vec<ProcessShare<'a>>,
+}
+
+/// The comparison made by the `diff` subcommand.
+#[derive(Debug, Serialize)]
+pub struct DiffReport<'a> {
+    pub schema_version: u32,
+    pub before: DiffSide<'a>,
+    pub after: DiffSide<'a>,
+    pub processes: Vec<ProcessChange<'a>>,
+    pub new_messages: Vec<MessageChange<'a>>,
+    pub vanished_messages: Vec<MessageChange<'a>>,
+    pub largest_absolute: Vec<MessageChange<'a>>,
+    pub largest_relative: Vec<MessageChange<'a>>,
+}
+
+/// One of the inputs or time windows compared.
+#[derive(Debug, Serialize)]
+pub struct DiffSide<'a> {
+    pub input: Cow<'a, str>,
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+    pub messages: u64,
+}
+
+/// `ratio` is the count after over the count before, null for new entries.
+#[derive(Debug, Serialize)]
+pub struct ProcessChange<'a> {
+    pub process: &'a str,
+    pub before: u32,
+    pub after: u32,
+    pub delta: i64,
+    pub ratio: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MessageChange<'a> {
+    pub process: &'a str,
+    pub priority: &'a str,
+    pub message: &'a str,
+    pub before: u32,
+    pub after: u32,
+    pub delta: i64,
+    pub ratio: Option<f64>,
+}
+
+/// Rank per process message counts, most messages first then by name.
+pub fn process_shares<'a, I>(counts: I, total: u64) -> Vec<ProcessShare<'a>>
+where
+    I: IntoIterator<Item = (&'a String, &'a u32)>,
+{
+    let mut counts: Vec<(&String, &u32)> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+
+    counts
+        .into_iter()
+        .enumerate()
+        .map(|(i, (process, messages))| ProcessShare {
+            rank: i + 1,
+            process,
+            messages: *messages,
+            percent: if total == 0 {
+                0.0
+            } else {
+                *messages as f64 / total as f64 * 100.0
+            },
+        })
+        .collect()
+}
+
+/// Rank byte counts, largest first then by name.
+pub fn byte_shares(sizes: &HashMap<String, u64>, total: u64) -> Vec<ByteShare<'_>> {
+    footprint::ranked(sizes)
+        .into_iter()
+        .enumerate()
+        .map(|(i, (name, bytes))| ByteShare {
+            rank: i + 1,
+            name,
+            bytes,
+            percent: if total == 0 {
+                0.0
+            } else {
+                bytes as f64 / total as f64 * 100.0
+            },
+        })
+        .collect()
+}
+
+/// Distinct values of the fields present in `counts`, in the order of
+/// `fields`.
+fn field_cardinalities<'a>(
+    fields: &'a [String],
+    counts: &HashMap<String, Distinct>,
+) -> Vec<FieldCardinality<'a>> {
+    fields
+        .iter()
+        .filter_map(|field| {
+            counts.get(field).map(|distinct| FieldCardinality {
+                field,
+                distinct: distinct.count(),
+                exact: distinct.is_exact(),
+            })
+        })
+        .collect()
+}
+
+/// Distinct values per field of `cardinality`, processes with the most
+/// values of a field first.
+pub fn cardinality_report(cardinality: &Cardinality) -> CardinalityReport<'_> {
+    let fields = cardinality.fields();
+    CardinalityReport {
+        overall: field_cardinalities(fields, cardinality.overall()),
+        per_process: cardinality
+            .per_process()
+            .into_iter()
+            .map(|(process, counts)| ProcessCardinality {
+                process,
+                fields: field_cardinalities(fields, counts),
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Tabled)]
+    #[allow(non_snake_case)]
+    struct Row<'a> {
+        Rank: usize,
+        Message: &'a str,
+    }
+
+    fn rows() -> Vec<Row<'static>> {
+        vec![
+            Row {
+                Rank: 1,
+                Message: "a, \"quoted\" | piped",
+            },
+            Row {
+                Rank: 2,
+                Message: "two\nlines",
+            },
+        ]
+    }
+
+    #[test]
+    fn csv_quotes_separators_and_line_breaks() {
+        assert_eq!(
+            table(OutputFormat::Csv, "Top 2 messages:", rows()),
+            "Top 2 messages\nRank,Message\n1,\"a, \"\"quoted\"\" | piped\"\n2,\"two\nlines\"\n"
+        );
+    }
+
+    #[test]
+    fn markdown_escapes_pipes_and_line_breaks() {
+        assert_eq!(
+            table(OutputFormat::Markdown, "Top 2 messages:", rows()),
+            "## Top 2 messages\n\n| Rank | Message |\n|---|---|\n| 1 | a, \"quoted\" \\| piped |\n| 2 | two<br>lines |\n"
+        );
+    }
+
+    #[test]
+    fn markdown_escapes_html() {
+        let rows = vec![Row {
+            Rank: 1,
+            Message: "Accepted from <IP> port <NUM> & more",
+        }];
+        assert_eq!(
+            table(OutputFormat::Markdown, "Top 1 templates:", rows),
+            "## Top 1 templates\n\n| Rank | Message |\n|---|---|\n| 1 | Accepted from &lt;IP&gt; port &lt;NUM&gt; &amp; more |\n"
+        );
+    }
+}
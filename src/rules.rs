@@ -0,0 +1,262 @@
+//! Threshold rules checked once the journal has been parsed, so that a
+//! pipeline or health check can fail on journal noise.
+//!
+//! A rule is `METRIC > LIMIT`, broken when the metric exceeds the limit:
+//!
+//! * `share > 20%`, any process logging more than 20% of the messages.
+//! * `messages > 100000`, the messages counted.
+//! * `process:NAME > 1000`, the messages of one process.
+//! * `priority:err > 10`, messages at a priority, a level including everything
+//!   more important or a range as with `--priority`.
+//! * `match:REGEX > 5`, messages matching a regex.
+//!
+//! Rules only see the messages left by the other filters.
+
+use crate::{priority::PriorityRange, Error, JournalStat, Message};
+use regex::Regex;
+use std::{fmt, fs, path::Path, str::FromStr};
+
+#[derive(Debug)]
+enum Metric {
+    Share,
+    Messages,
+    Process(String),
+    Priority(PriorityRange),
+    Match(Regex),
+}
+
+/// A limit on one of the statistics.
+#[derive(Debug)]
+pub struct Rule {
+    // The rule as written, for reporting.
+    text: String,
+    metric: Metric,
+    // A percentage for shares, a count otherwise.
+    limit: f64,
+}
+
+impl FromStr for Rule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Split on the last '>' so that regexes may contain one.
+        let (metric, limit) = s
+            .rsplit_once('>')
+            .ok_or_else(|| format!("invalid rule '{}', expected METRIC > LIMIT", s))?;
+        let (metric, limit) = (metric.trim(), limit.trim());
+
+        let metric = match metric.split_once(':') {
+            None if metric == "share" => Metric::Share,
+            None if metric == "messages" => Metric::Messages,
+            Some(("process", name)) => Metric::Process(name.to_string()),
+            Some(("priority", range)) => Metric::Priority(range.parse()?),
+            Some(("match", regex)) => Metric::Match(
+                Regex::new(regex).map_err(|e| format!("invalid regex in rule '{}': {}", s, e))?,
+            ),
+            _ => {
+                return Err(format!(
+                    "unknown metric '{}' in rule '{}', expected share, messages, process:NAME, priority:LEVEL or match:REGEX",
+                    metric, s
+                ))
+            }
+        };
+
+        let number = match metric {
+            Metric::Share => limit.strip_suffix('%').unwrap_or(limit),
+            _ => limit,
+        };
+        let limit = number
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|limit| *limit >= 0.0)
+            .ok_or_else(|| format!("invalid limit '{}' in rule '{}'", limit, s))?;
+
+        Ok(Rule {
+            text: s.trim().to_string(),
+            metric,
+            limit,
+        })
+    }
+}
+
+/// Read rules from a file, one per line. Blank lines and lines starting with
+/// `#` are skipped.
+pub fn load(path: &Path) -> Result<Vec<Rule>, String> {
+    let text =
+        fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+
+    text.lines()
+        .enumerate()
+        .map(|(i, line)| (i, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(i, line)| {
+            line.parse()
+                .map_err(|e| format!("{}:{}: {}", path.display(), i + 1, e))
+        })
+        .collect()
+}
+
+/// A rule broken by the statistics.
+#[derive(Debug, PartialEq)]
+pub struct Violation<'a> {
+    pub rule: &'a str,
+    /// The process over the limit, for per process rules.
+    pub process: Option<&'a str>,
+    pub value: f64,
+}
+
+impl fmt::Display for Violation<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.process {
+            Some(process) => write!(
+                f,
+                "rule '{}' broken: {} logged {:.2}% of messages",
+                self.rule, process, self.value
+            ),
+            None => write!(f, "rule '{}' broken: {}", self.rule, self.value),
+        }
+    }
+}
+
+impl Rule {
+    /// Whether the rule counts messages by their priority or text, which
+    /// needs every distinct message counted and so exact counting.
+    pub fn needs_exact_counts(&self) -> bool {
+        matches!(self.metric, Metric::Priority(_) | Metric::Match(_))
+    }
+
+    /// The ways `stat` breaks the rule, one per process over the limit for
+    /// per process rules. Rules that need exact counts cannot be checked
+    /// against approximate ones.
+    pub fn check<'a>(&'a self, stat: &'a JournalStat) -> crate::Result<Vec<Violation<'a>>> {
+        if self.needs_exact_counts() && stat.messages().is_approximate() {
+            return Err(Error::Invalid(format!(
+                "rule '{}' needs exact message counts, it cannot be used with --approximate",
+                self.text
+            )));
+        }
+
+        let count = |keep: &dyn Fn(&Message) -> bool| -> f64 {
+            stat.message_counts()
+                .iter()
+                .filter(|(message, _)| keep(message))
+                .map(|(_, count)| *count as f64)
+                .sum()
+        };
+
+        let value = match &self.metric {
+            Metric::Share => {
+                let mut violations: Vec<Violation> = stat
+                    .per_process()
+                    .iter()
+                    .map(|(process, count)| Violation {
+                        rule: &self.text,
+                        process: Some(process),
+                        value: *count as f64 / stat.total_messages().max(1) as f64 * 100.0,
+                    })
+                    .filter(|v| v.value > self.limit)
+                    .collect();
+                violations
+                    .sort_by(|a, b| b.value.total_cmp(&a.value).then(a.process.cmp(&b.process)));
+                return Ok(violations);
+            }
+            Metric::Messages => stat.total_messages() as f64,
+            Metric::Process(name) => stat.per_process().get(name).copied().unwrap_or(0) as f64,
+            Metric::Priority(range) => count(&|m| range.contains(&m.priority)),
+            Metric::Match(regex) => count(&|m| regex.is_match(&m.msg)),
+        };
+
+        if value > self.limit {
+            Ok(vec![Violation {
+                rule: &self.text,
+                process: None,
+                value,
+            }])
+        } else {
+            Ok(Vec::new())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::Entry;
+    use std::num::NonZeroUsize;
+
+    #[test]
+    fn parses_rules() {
+        let rule: Rule = "share > 20%".parse().unwrap();
+        assert!(matches!(rule.metric, Metric::Share));
+        assert_eq!(rule.limit, 20.0);
+
+        let rule: Rule = "match:a>b > 5".parse().unwrap();
+        assert!(matches!(&rule.metric, Metric::Match(re) if re.as_str() == "a>b"));
+        assert_eq!(rule.limit, 5.0);
+
+        assert!(matches!(
+            "priority:warning..emerg > 1"
+                .parse::<Rule>()
+                .unwrap()
+                .metric,
+            Metric::Priority(PriorityRange { min: 0, max: 4 })
+        ));
+        assert!("share".parse::<Rule>().is_err());
+        assert!("bogus > 1".parse::<Rule>().is_err());
+        assert!("messages > many".parse::<Rule>().is_err());
+        assert!("match:( > 1".parse::<Rule>().is_err());
+    }
+
+    #[test]
+    fn reports_broken_rules() {
+        let entries: Vec<Entry> = [
+            ("disk full", "app", "3"),
+            ("disk full", "app", "3"),
+            ("started", "app", "6"),
+            ("tick", "cron", "6"),
+        ]
+        .iter()
+        .map(|(msg, process, priority)| {
+            [("MESSAGE", msg), ("_COMM", process), ("PRIORITY", priority)]
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        })
+        .collect();
+        let mut stat = JournalStat::new(Path::new("test"), Box::new(entries.clone().into_iter()));
+        stat.parse().unwrap();
+
+        let broken = |rule: &str| -> Vec<String> {
+            let rule: Rule = rule.parse().unwrap();
+            let violations = rule.check(&stat).unwrap();
+            violations.iter().map(|v| v.to_string()).collect()
+        };
+
+        assert_eq!(
+            broken("share > 20%"),
+            [
+                "rule 'share > 20%' broken: app logged 75.00% of messages",
+                "rule 'share > 20%' broken: cron logged 25.00% of messages"
+            ]
+        );
+        assert_eq!(broken("share > 75%"), Vec::<String>::new());
+        assert_eq!(
+            broken("priority:err > 1"),
+            ["rule 'priority:err > 1' broken: 2"]
+        );
+        assert_eq!(broken("match:^disk > 2"), Vec::<String>::new());
+        assert_eq!(broken("process:cron > 0").len(), 1);
+        assert_eq!(broken("messages > 4"), Vec::<String>::new());
+
+        // Approximate counts only track some messages.
+        let mut stat = JournalStat::new(Path::new("test"), Box::new(entries.into_iter()));
+        stat.approximate(NonZeroUsize::new(2)).parse().unwrap();
+        let rule: Rule = "priority:err > 1".parse().unwrap();
+        assert!(rule.check(&stat).is_err());
+        let rule: Rule = "share > 20%".parse().unwrap();
+        assert_eq!(rule.check(&stat).unwrap().len(), 2);
+        let report = serde_json::to_value(stat.report_document()).unwrap();
+        assert!(report["totals"]["distinct_messages"].is_null());
+    }
+}
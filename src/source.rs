@@ -0,0 +1,307 @@
+//! Sources of journal entries.
+//!
+//! The statistics in `JournalStat` only ever look at the field map of each
+//! entry, so any reader that can produce those maps can feed them.
+
+use crate::{export::ExportReader, json::JsonReader};
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, BufRead, BufReader},
+    path::{Path, PathBuf},
+    str::FromStr,
+    thread,
+    time::Duration,
+};
+
+/// A single journal entry, mapping field names to their values.
+pub type Entry = BTreeMap<String, String>;
+
+/// The field holding the wallclock time of an entry, in microseconds since
+/// the epoch. Binary journal readers add it so every source provides it.
+pub const REALTIME_TIMESTAMP: &str = "__REALTIME_TIMESTAMP";
+
+/// The realtime timestamp of an entry, if it has a valid one. Zero, which
+/// readers may write for a time they could not get, counts as none.
+pub fn timestamp(entry: &Entry) -> Option<u64> {
+    entry
+        .get(REALTIME_TIMESTAMP)
+        .and_then(|t| t.parse().ok())
+        .filter(|ts| *ts > 0)
+}
+
+/// Something that produces journal entries in order.
+pub trait EntrySource {
+    /// Read the next entry, returning `Ok(None)` once there are no more.
+    fn next_entry(&mut self) -> io::Result<Option<Entry>>;
+
+    /// Skip ahead to the first entry at or after `usec` microseconds since
+    /// the epoch. Sources that cannot seek leave it to the caller to skip
+    /// earlier entries.
+    fn seek_realtime(&mut self, _usec: u64) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Restrict the source to entries matching any of `groups`, where each
+    /// group is a set of `FIELD=VALUE` pairs with journalctl semantics.
+    /// Returns false if the source cannot filter, leaving it to the caller.
+    /// On error no match is left applied.
+    fn add_matches(&mut self, _groups: &[Vec<(&str, &str)>]) -> io::Result<bool> {
+        Ok(false)
+    }
+
+    /// Once `next_entry` has returned `Ok(None)`, wait up to `timeout` for
+    /// more entries to be written. Sources that cannot be told about new
+    /// entries just sleep.
+    fn wait(&mut self, timeout: Duration) -> io::Result<()> {
+        thread::sleep(timeout);
+        Ok(())
+    }
+
+    /// Take the problems met since last asked that did not stop the source,
+    /// such as journal files it skipped.
+    fn take_warnings(&mut self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+#[cfg(test)]
+impl EntrySource for std::vec::IntoIter<Entry> {
+    fn next_entry(&mut self) -> io::Result<Option<Entry>> {
+        Ok(self.next())
+    }
+}
+
+#[cfg(feature = "libsystemd")]
+impl EntrySource for systemd::Journal {
+    fn next_entry(&mut self) -> io::Result<Option<Entry>> {
+        // Deref to the inner journal so this calls the inherent method.
+        let mut entry = match (**self).next_entry()? {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+
+        // Entries dated before the epoch are left without a timestamp.
+        if let Ok(since_epoch) = self.timestamp()?.duration_since(std::time::UNIX_EPOCH) {
+            let usec = since_epoch.as_micros() as u64;
+            entry.insert(REALTIME_TIMESTAMP.to_string(), usec.to_string());
+        }
+
+        Ok(Some(entry))
+    }
+
+    fn seek_realtime(&mut self, usec: u64) -> io::Result<()> {
+        self.seek_realtime_usec(usec)
+    }
+
+    fn add_matches(&mut self, groups: &[Vec<(&str, &str)>]) -> io::Result<bool> {
+        let added = groups.iter().enumerate().try_for_each(|(i, group)| {
+            if i > 0 {
+                self.match_or()?;
+            }
+            for (field, value) in group {
+                self.match_add(field, *value)?;
+            }
+            Ok(())
+        });
+
+        // A partial conjunction would drop entries the full expression
+        // accepts.
+        if let Err(e) = added {
+            self.match_flush()?;
+            return Err(e);
+        }
+
+        Ok(true)
+    }
+
+    fn wait(&mut self, timeout: Duration) -> io::Result<()> {
+        (**self).wait(Some(timeout)).map(|_| ())
+    }
+}
+
+/// The reader used to open binary journal files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// Read through libsystemd's `sd_journal` API.
+    Libsystemd,
+    /// Read with the built in journal file parser.
+    Native,
+}
+
+impl Default for Backend {
+    fn default() -> Self {
+        if cfg!(feature = "libsystemd") {
+            Backend::Libsystemd
+        } else {
+            Backend::Native
+        }
+    }
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "libsystemd" => Ok(Backend::Libsystemd),
+            "native" => Ok(Backend::Native),
+            _ => Err(format!(
+                "unknown backend '{}', expected 'libsystemd' or 'native'",
+                s
+            )),
+        }
+    }
+}
+
+/// The format of the input.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum InputFormat {
+    /// Work it out from the input itself.
+    #[default]
+    Auto,
+    /// Binary journal files.
+    Journal,
+    /// The Journal Export Format, `journalctl -o export`.
+    Export,
+    /// One JSON object per line, `journalctl -o json`.
+    Json,
+}
+
+impl FromStr for InputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(InputFormat::Auto),
+            "journal" => Ok(InputFormat::Journal),
+            "export" => Ok(InputFormat::Export),
+            "json" => Ok(InputFormat::Json),
+            _ => Err(format!(
+                "unknown input format '{}', expected 'auto', 'journal', 'export' or 'json'",
+                s
+            )),
+        }
+    }
+}
+
+/// Guess the format of a stream from its first bytes.
+fn sniff(buf: &[u8]) -> InputFormat {
+    if buf.starts_with(b"LPKSHHRH") {
+        InputFormat::Journal
+    } else if buf.iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'{') {
+        InputFormat::Json
+    } else {
+        InputFormat::Export
+    }
+}
+
+/// Wrap a text stream in the reader for its format.
+fn stream<R: BufRead + 'static>(
+    mut reader: R,
+    format: InputFormat,
+) -> io::Result<Box<dyn EntrySource>> {
+    let format = match format {
+        InputFormat::Auto => sniff(reader.fill_buf()?),
+        format => format,
+    };
+
+    match format {
+        InputFormat::Json => Ok(Box::new(JsonReader::new(reader))),
+        InputFormat::Export => Ok(Box::new(ExportReader::new(reader))),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "binary journals cannot be read from a stream",
+        )),
+    }
+}
+
+/// Open the input at `path` in the given format, `-` reads from stdin.
+///
+/// Directories and binary journal files are read with `backend`.
+pub fn open(
+    path: &Path,
+    format: InputFormat,
+    backend: Backend,
+) -> io::Result<Box<dyn EntrySource>> {
+    if path == Path::new("-") {
+        return stream(io::stdin().lock(), format);
+    }
+
+    if path.is_dir() {
+        return open_journal(path, backend);
+    }
+
+    match format {
+        InputFormat::Journal => open_journal(path, backend),
+        InputFormat::Auto => {
+            let mut reader = BufReader::new(File::open(path)?);
+            if sniff(reader.fill_buf()?) == InputFormat::Journal {
+                open_journal(path, backend)
+            } else {
+                stream(reader, format)
+            }
+        }
+        format => stream(BufReader::new(File::open(path)?), format),
+    }
+}
+
+/// The journal files in a directory and its subdirectories, in name order.
+pub fn journal_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for dirent in fs::read_dir(dir)? {
+        let dirent = dirent?;
+        if dirent.file_type()?.is_dir() {
+            for sub in fs::read_dir(dirent.path())? {
+                paths.push(sub?.path());
+            }
+        } else {
+            paths.push(dirent.path());
+        }
+    }
+    paths.retain(|p| {
+        p.file_name()
+            .and_then(|n| n.to_str())
+            .is_some_and(|n| n.ends_with(".journal") || n.ends_with(".journal~"))
+    });
+    paths.sort();
+
+    Ok(paths)
+}
+
+/// Open a journal file, or a directory of journal files, with the given backend.
+fn open_journal(path: &Path, backend: Backend) -> io::Result<Box<dyn EntrySource>> {
+    match backend {
+        #[cfg(feature = "libsystemd")]
+        Backend::Libsystemd => {
+            use systemd::journal::{OpenDirectoryOptions, OpenFilesOptions};
+
+            let name = path.to_str().ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, "path is not valid UTF-8")
+            })?;
+            let journal = if path.is_dir() {
+                OpenDirectoryOptions::default().open_directory(name)
+            } else {
+                OpenFilesOptions::default().open_files(Some(name))
+            }?;
+
+            Ok(Box::new(journal))
+        }
+        #[cfg(feature = "native")]
+        Backend::Native => {
+            if path.is_dir() {
+                Ok(Box::new(crate::native::JournalDirectory::open(path)?))
+            } else {
+                Ok(Box::new(crate::native::JournalFile::open(path)?))
+            }
+        }
+        #[allow(unreachable_patterns)]
+        _ => {
+            let _ = path;
+            Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("journalstat was built without the {:?} backend", backend),
+            ))
+        }
+    }
+}
@@ -0,0 +1,480 @@
+//! Grouping of messages that differ only in their variable parts.
+//!
+//! Two modes are supported. `mask` replaces numbers, addresses, IDs, paths
+//! and timestamps with placeholders and groups messages on the result.
+//! `drain` masks the same way and then runs a simplified version of the Drain
+//! log parser (He et al., ICWS 2017): messages with the same process, token
+//! count and first token are compared token by token, and are merged into an
+//! existing template when enough tokens agree, the tokens that differ
+//! becoming a `<*>` wildcard.
+
+use crate::{
+    analyzer::{self, Analyzer, Section},
+    report::{self, OutputFormat, Report},
+    source::Entry,
+    Error, Message,
+};
+use regex::{Captures, Regex};
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    hash::{Hash, Hasher},
+    io::{self, Write},
+    str::FromStr,
+};
+use tabled::Tabled;
+
+/// Wildcard used by Drain for tokens that vary within a template.
+const WILDCARD: &str = "<*>";
+
+/// Share of tokens that must agree for a message to join a Drain template.
+const SIMILARITY_THRESHOLD: f64 = 0.5;
+
+/// How messages are grouped into templates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClusterMode {
+    /// Replace variable parts with placeholders.
+    Mask,
+    /// Mask, then merge similar messages with the Drain algorithm.
+    Drain,
+}
+
+impl FromStr for ClusterMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mask" => Ok(ClusterMode::Mask),
+            "drain" => Ok(ClusterMode::Drain),
+            _ => Err(format!(
+                "unknown cluster mode '{}', expected 'mask' or 'drain'",
+                s
+            )),
+        }
+    }
+}
+
+/// Replaces the variable parts of messages with placeholders.
+#[derive(Clone)]
+pub struct Masker {
+    rules: Vec<(Regex, &'static str)>,
+    hex: Regex,
+}
+
+impl Default for Masker {
+    fn default() -> Self {
+        // Order matters, more specific patterns must run first.
+        let rules = [
+            (
+                r"\b[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}\b",
+                "<UUID>",
+            ),
+            (
+                r"\b\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}:\d{2}(?:[.,]\d+)?(?:Z|[+-]\d{2}:?\d{2})?",
+                "<TIME>",
+            ),
+            (r"\b\d{2}:\d{2}:\d{2}(?:\.\d+)?\b", "<TIME>"),
+            (r"\b(?:[0-9a-fA-F]{2}:){5}[0-9a-fA-F]{2}\b", "<MAC>"),
+            (r"\b\d{1,3}(?:\.\d{1,3}){3}(?::\d+)?\b", "<IP>"),
+            (
+                r"\b[0-9a-fA-F]{1,4}(?::[0-9a-fA-F]{0,4}){2,7}\b|::(?:[0-9a-fA-F]{1,4}:){0,6}[0-9a-fA-F]{1,4}\b",
+                "<IP>",
+            ),
+            (r#"(?P<pre>^|[\s=('"])/[^\s:,;'"()]+"#, "${pre}<PATH>"),
+            (r"\b0[xX][0-9a-fA-F]+\b", "<HEX>"),
+        ];
+
+        Self {
+            rules: rules
+                .iter()
+                .map(|(re, rep)| (Regex::new(re).unwrap(), *rep))
+                .collect(),
+            // Long runs of hex digits are IDs, unless they are all digits.
+            hex: Regex::new(r"\b[0-9a-fA-F]{8,}\b|\b\d+(?:\.\d+)?\b").unwrap(),
+        }
+    }
+}
+
+impl Masker {
+    /// Mask the variable parts of `msg`.
+    pub fn mask(&self, msg: &str) -> String {
+        let mut masked = msg.to_string();
+        for (re, rep) in &self.rules {
+            masked = re.replace_all(&masked, *rep).into_owned();
+        }
+
+        self.hex
+            .replace_all(&masked, |caps: &Captures| {
+                if caps[0].bytes().all(|b| b.is_ascii_digit() || b == b'.') {
+                    "<NUM>"
+                } else {
+                    "<HEX>"
+                }
+            })
+            .into_owned()
+    }
+}
+
+/// Statistics for one message template.
+#[derive(Debug, Clone)]
+pub struct Template {
+    /// The process logging the messages.
+    pub process: String,
+    /// The message with its variable parts replaced.
+    pub template: String,
+    /// The first message seen for the template.
+    pub sample: String,
+    /// Number of messages matching the template.
+    pub count: u32,
+    // Hashes of the distinct messages matching the template.
+    variants: HashSet<u64>,
+}
+
+impl Template {
+    /// Number of distinct messages matching the template.
+    pub fn variants(&self) -> usize {
+        self.variants.len()
+    }
+
+    fn add(&mut self, msg: &str) {
+        let mut hasher = DefaultHasher::new();
+        msg.hash(&mut hasher);
+        self.variants.insert(hasher.finish());
+        self.count += 1;
+    }
+}
+
+/// A Drain cluster, the tokens of its template and its statistics.
+struct Cluster {
+    tokens: Vec<String>,
+    template: usize,
+}
+
+/// Groups messages into templates and counts them.
+pub struct TemplateMiner {
+    mode: ClusterMode,
+    // Number of templates reported.
+    top: usize,
+    masker: Masker,
+    templates: Vec<Template>,
+    // Mask mode, template index by process and masked message.
+    masked: HashMap<(String, String), usize>,
+    // Drain mode, clusters by process, token count and first token.
+    clusters: HashMap<(String, usize, String), Vec<Cluster>>,
+}
+
+impl TemplateMiner {
+    /// Group messages with `mode`, reporting the `top` most frequent
+    /// templates.
+    pub fn new(mode: ClusterMode, top: usize) -> Self {
+        Self {
+            mode,
+            top,
+            masker: Masker::default(),
+            templates: Vec::new(),
+            masked: HashMap::new(),
+            clusters: HashMap::new(),
+        }
+    }
+
+    pub fn mode(&self) -> ClusterMode {
+        self.mode
+    }
+
+    fn new_template(&mut self, process: &str, template: String, msg: &str) -> usize {
+        self.templates.push(Template {
+            process: process.to_string(),
+            template,
+            sample: msg.to_string(),
+            count: 0,
+            variants: HashSet::new(),
+        });
+        self.templates.len() - 1
+    }
+
+    /// Find or create the Drain template for the masked message.
+    fn drain(&mut self, process: &str, masked: &str, msg: &str) -> usize {
+        let tokens: Vec<String> = masked.split_whitespace().map(String::from).collect();
+        let first = match tokens.first() {
+            // Tokens with digits are likely variable, do not split on them.
+            Some(t) if !t.bytes().any(|b| b.is_ascii_digit()) => t.clone(),
+            _ => WILDCARD.to_string(),
+        };
+        let key = (process.to_string(), tokens.len(), first);
+
+        let best = self.clusters.get(&key).and_then(|clusters| {
+            clusters
+                .iter()
+                .enumerate()
+                .map(|(i, c)| (i, similarity(&c.tokens, &tokens)))
+                .filter(|(_, sim)| *sim >= SIMILARITY_THRESHOLD)
+                .max_by(|a, b| a.1.total_cmp(&b.1))
+                .map(|(i, _)| i)
+        });
+
+        match best {
+            Some(i) => {
+                let cluster = &mut self.clusters.get_mut(&key).unwrap()[i];
+                for (t, new) in cluster.tokens.iter_mut().zip(&tokens) {
+                    if t != new {
+                        *t = WILDCARD.to_string();
+                    }
+                }
+                let idx = cluster.template;
+                self.templates[idx].template = cluster.tokens.join(" ");
+                idx
+            }
+            None => {
+                let idx = self.new_template(process, tokens.join(" "), msg);
+                self.clusters.entry(key).or_default().push(Cluster {
+                    tokens,
+                    template: idx,
+                });
+                idx
+            }
+        }
+    }
+
+    /// Count a message logged by `process`.
+    pub fn add(&mut self, process: &str, msg: &str) {
+        let masked = self.masker.mask(msg);
+
+        let idx = match self.mode {
+            ClusterMode::Mask => match self.masked.get(&(process.to_string(), masked.clone())) {
+                Some(idx) => *idx,
+                None => {
+                    let idx = self.new_template(process, masked.clone(), msg);
+                    self.masked.insert((process.to_string(), masked), idx);
+                    idx
+                }
+            },
+            ClusterMode::Drain => self.drain(process, &masked, msg),
+        };
+
+        self.templates[idx].add(msg);
+    }
+
+    /// The `n` most frequent templates, ties broken by template then process.
+    pub fn top(&self, n: usize) -> Vec<&Template> {
+        let mut top: Vec<&Template> = self.templates.iter().collect();
+        top.sort_by(|a, b| {
+            b.count
+                .cmp(&a.count)
+                .then_with(|| a.template.cmp(&b.template))
+                .then_with(|| a.process.cmp(&b.process))
+        });
+        top.truncate(n);
+        top
+    }
+}
+
+#[derive(Tabled)]
+#[allow(non_snake_case)]
+struct TemplateTableEntry<'a> {
+    Rank: usize,
+    Frequency: u32,
+    Variants: usize,
+    Process: &'a str,
+    Template: &'a str,
+    Sample: &'a str,
+}
+
+impl Analyzer for TemplateMiner {
+    fn name(&self) -> &str {
+        "templates"
+    }
+
+    fn observe(&mut self, message: &Message, _entry: &Entry) {
+        self.add(&message.process, &message.msg);
+    }
+
+    fn fork(&self) -> Box<dyn Analyzer> {
+        Box::new(Self::new(self.mode, self.top))
+    }
+
+    /// Add the templates counted by `other`. Only mask mode templates can be
+    /// merged, Drain clusters depend on the order messages are seen in, and
+    /// merging any others is an error.
+    fn merge(&mut self, other: Box<dyn Analyzer>) -> crate::Result<()> {
+        let other = analyzer::downcast::<Self>(other)?;
+        if !self.mergeable() || !other.mergeable() {
+            return Err(Error::Invalid(
+                "Drain templates depend on the order of messages and cannot be merged".to_string(),
+            ));
+        }
+
+        for theirs in other.templates {
+            let key = (theirs.process.clone(), theirs.template.clone());
+            match self.masked.get(&key) {
+                Some(idx) => {
+                    let mine = &mut self.templates[*idx];
+                    mine.count += theirs.count;
+                    mine.variants.extend(theirs.variants);
+                }
+                None => {
+                    self.masked.insert(key, self.templates.len());
+                    self.templates.push(theirs);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn mergeable(&self) -> bool {
+        self.mode == ClusterMode::Mask
+    }
+
+    fn finish(&self) -> Section {
+        let top = self.top(self.top);
+        Section {
+            name: self.name().to_string(),
+            title: format!("Top {} most frequent message templates:", top.len()),
+            columns: [
+                "Rank",
+                "Frequency",
+                "Variants",
+                "Process",
+                "Template",
+                "Sample",
+            ]
+            .map(String::from)
+            .to_vec(),
+            rows: top
+                .into_iter()
+                .enumerate()
+                .map(|(i, t)| {
+                    vec![
+                        (i + 1).into(),
+                        t.count.into(),
+                        t.variants().into(),
+                        t.process.as_str().into(),
+                        t.template.as_str().into(),
+                        t.sample.as_str().into(),
+                    ]
+                })
+                .collect(),
+        }
+    }
+
+    fn report<'a>(&'a self, document: &mut Report<'a>) {
+        document.templates = Some(
+            self.top(self.top)
+                .into_iter()
+                .enumerate()
+                .map(|(i, t)| report::TemplateCount {
+                    rank: i + 1,
+                    count: t.count,
+                    variants: t.variants(),
+                    process: &t.process,
+                    template: &t.template,
+                    sample: &t.sample,
+                })
+                .collect(),
+        );
+    }
+
+    fn write_tables(&self, format: OutputFormat, out: &mut dyn Write) -> io::Result<()> {
+        let top = self.top(self.top);
+        if top.is_empty() {
+            return Ok(());
+        }
+
+        let table = top
+            .iter()
+            .enumerate()
+            .map(|(i, template)| TemplateTableEntry {
+                Rank: i + 1,
+                Frequency: template.count,
+                Variants: template.variants(),
+                Process: &template.process,
+                Template: &template.template,
+                Sample: &template.sample,
+            })
+            .collect();
+
+        writeln!(
+            out,
+            "{}",
+            report::table(
+                format,
+                &format!("Top {} most frequent message templates:", top.len()),
+                table
+            )
+        )
+    }
+}
+
+/// Share of positions where the template and message tokens agree.
+fn similarity(template: &[String], tokens: &[String]) -> f64 {
+    if tokens.is_empty() {
+        return 1.0;
+    }
+
+    let same = template
+        .iter()
+        .zip(tokens)
+        .filter(|(t, m)| t == m || t.as_str() == WILDCARD)
+        .count();
+
+    same as f64 / tokens.len() as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn masks_variable_parts() {
+        let m = Masker::default();
+
+        assert_eq!(
+            m.mask("Connection from 10.0.0.1 port 5123"),
+            "Connection from <IP> port <NUM>"
+        );
+        assert_eq!(
+            m.mask("session 3f2504e0-4f89-11d3-9a0c-0305e82c3301 opened at 2023-05-01T12:00:00Z"),
+            "session <UUID> opened at <TIME>"
+        );
+        assert_eq!(
+            m.mask("read /var/lib/foo.db failed at 0xdeadbeef, id cafe1234beef"),
+            "read <PATH> failed at <HEX>, id <HEX>"
+        );
+        assert_eq!(
+            m.mask("eth0 link up, fe80::1ff:fe23:4567:890a dev 00:1a:2b:3c:4d:5e took 12.5 ms"),
+            "eth0 link up, <IP> dev <MAC> took <NUM> ms"
+        );
+    }
+
+    #[test]
+    fn mask_mode_groups_on_masked_message() {
+        let mut miner = TemplateMiner::new(ClusterMode::Mask, 10);
+        miner.add("sshd", "Connection from 10.0.0.1 port 5123");
+        miner.add("sshd", "Connection from 10.0.0.2 port 5124");
+        miner.add("sshd", "Connection from 10.0.0.2 port 5124");
+        miner.add("other", "Connection from 10.0.0.1 port 5123");
+
+        let top = miner.top(10);
+        assert_eq!(top.len(), 2);
+        assert_eq!(top[0].process, "sshd");
+        assert_eq!(top[0].template, "Connection from <IP> port <NUM>");
+        assert_eq!(top[0].sample, "Connection from 10.0.0.1 port 5123");
+        assert_eq!((top[0].count, top[0].variants()), (3, 2));
+    }
+
+    #[test]
+    fn drain_mode_merges_similar_messages() {
+        let mut miner = TemplateMiner::new(ClusterMode::Drain, 10);
+        miner.add("login", "User alice logged in");
+        miner.add("login", "User bob logged in");
+        miner.add("login", "User carol logged out");
+        miner.add("login", "Disk quota exceeded");
+
+        let top = miner.top(10);
+        assert_eq!(top[0].template, "User <*> logged <*>");
+        assert_eq!((top[0].count, top[0].variants()), (3, 3));
+        assert_eq!(top[1].template, "Disk quota exceeded");
+
+        let fork = miner.fork();
+        assert!(!miner.mergeable());
+        assert!(miner.merge(fork).is_err());
+    }
+}
@@ -0,0 +1,200 @@
+//! Parsing and formatting of the timestamps used with `--since` and `--until`,
+//! and of durations such as the `--histogram` interval.
+//!
+//! Timestamps are handled as microseconds since the epoch, the same unit as
+//! the journal's `__REALTIME_TIMESTAMP` field.
+
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone};
+
+/// Formats accepted for absolute timestamps, interpreted in local time.
+const DATETIME_FORMATS: &[&str] = &[
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%d %H:%M",
+    "%Y-%m-%dT%H:%M:%S",
+    "%Y-%m-%dT%H:%M",
+];
+
+fn usec(time: DateTime<Local>) -> u64 {
+    time.timestamp_micros().max(0) as u64
+}
+
+fn midnight(date: NaiveDate) -> Result<u64, String> {
+    Local
+        .from_local_datetime(&date.and_time(NaiveTime::MIN))
+        .earliest()
+        .map(usec)
+        .ok_or_else(|| format!("no local midnight on {}", date))
+}
+
+/// Parse a relative time such as "-2h", "+30min" or "1d 12h ago".
+fn parse_relative(s: &str) -> Option<Duration> {
+    let (sign, rest) = if let Some(rest) = s.strip_prefix('-') {
+        (-1, rest)
+    } else if let Some(rest) = s.strip_prefix('+') {
+        (1, rest)
+    } else if let Some(rest) = s.strip_suffix("ago") {
+        (-1, rest)
+    } else {
+        return None;
+    };
+
+    parse_duration(rest).map(|total| total * sign)
+}
+
+/// Parse a duration such as "90s", "15min" or "1d 12h".
+pub fn parse_duration(s: &str) -> Option<Duration> {
+    let mut total = Duration::zero();
+    let mut rest = s.trim();
+    if rest.is_empty() {
+        return None;
+    }
+
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let value: i64 = rest[..digits].parse().ok()?;
+        rest = rest[digits..].trim_start();
+
+        let unit_len = rest
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(rest.len());
+        let unit = match &rest[..unit_len] {
+            "us" | "usec" => Duration::microseconds(value),
+            "ms" | "msec" => Duration::milliseconds(value),
+            "" | "s" | "sec" | "second" | "seconds" => Duration::seconds(value),
+            "m" | "min" | "minute" | "minutes" => Duration::minutes(value),
+            "h" | "hr" | "hour" | "hours" => Duration::hours(value),
+            "d" | "day" | "days" => Duration::days(value),
+            "w" | "week" | "weeks" => Duration::weeks(value),
+            _ => return None,
+        };
+        total += unit;
+        rest = rest[unit_len..].trim_start();
+    }
+
+    Some(total)
+}
+
+/// Parse a timestamp in the style accepted by `journalctl --since`.
+///
+/// Accepts absolute local times ("2023-05-01 12:00:00", "2023-05-01"),
+/// seconds since the epoch ("@1682942400"), "now", "today", "yesterday",
+/// "tomorrow" and times relative to `now` ("-2h", "+1d", "30min ago").
+pub fn parse_time(s: &str, now: DateTime<Local>) -> Result<u64, String> {
+    let s = s.trim();
+    let today = now.date_naive();
+
+    match s {
+        "now" => return Ok(usec(now)),
+        "today" => return midnight(today),
+        "yesterday" => return midnight(today - Duration::days(1)),
+        "tomorrow" => return midnight(today + Duration::days(1)),
+        _ => {}
+    }
+
+    if let Some(epoch) = s.strip_prefix('@') {
+        let secs: f64 = epoch
+            .parse()
+            .map_err(|_| format!("invalid epoch timestamp '{}'", s))?;
+        return Ok((secs * 1_000_000.0).max(0.0) as u64);
+    }
+
+    if let Some(delta) = parse_relative(s) {
+        return Ok(usec(now + delta));
+    }
+
+    for format in DATETIME_FORMATS {
+        if let Ok(time) = NaiveDateTime::parse_from_str(s, format) {
+            return Local
+                .from_local_datetime(&time)
+                .earliest()
+                .map(usec)
+                .ok_or_else(|| format!("'{}' does not exist in local time", s));
+        }
+    }
+
+    if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        return midnight(date);
+    }
+
+    Err(format!("invalid timestamp '{}'", s))
+}
+
+/// The current time in microseconds since the epoch.
+pub fn now() -> u64 {
+    Local::now().timestamp_micros().max(0) as u64
+}
+
+/// Format a timestamp in microseconds since the epoch as local time.
+pub fn format_time(usec: u64) -> String {
+    match Local.timestamp_micros(usec as i64).single() {
+        Some(time) => time.format("%Y-%m-%d %H:%M:%S%.6f %z").to_string(),
+        None => format!("@{}", usec),
+    }
+}
+
+/// Format a timestamp in microseconds since the epoch as local time, to the
+/// minute.
+pub fn format_minute(usec: u64) -> String {
+    match Local.timestamp_micros(usec as i64).single() {
+        Some(time) => time.format("%Y-%m-%d %H:%M").to_string(),
+        None => format!("@{}", usec),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn now() -> DateTime<Local> {
+        Local.with_ymd_and_hms(2023, 5, 10, 15, 30, 0).unwrap()
+    }
+
+    fn at(y: i32, mo: u32, d: u32, h: u32, mi: u32, s: u32) -> u64 {
+        usec(Local.with_ymd_and_hms(y, mo, d, h, mi, s).unwrap())
+    }
+
+    #[test]
+    fn parses_absolute_times() {
+        assert_eq!(
+            parse_time("2023-05-01 12:00:00", now()),
+            Ok(at(2023, 5, 1, 12, 0, 0))
+        );
+        assert_eq!(
+            parse_time("2023-05-01 12:00", now()),
+            Ok(at(2023, 5, 1, 12, 0, 0))
+        );
+        assert_eq!(parse_time("2023-05-01", now()), Ok(at(2023, 5, 1, 0, 0, 0)));
+        assert_eq!(parse_time("@1.5", now()), Ok(1_500_000));
+    }
+
+    #[test]
+    fn parses_keywords_and_relative_times() {
+        assert_eq!(parse_time("now", now()), Ok(usec(now())));
+        assert_eq!(parse_time("today", now()), Ok(at(2023, 5, 10, 0, 0, 0)));
+        assert_eq!(parse_time("yesterday", now()), Ok(at(2023, 5, 9, 0, 0, 0)));
+        assert_eq!(parse_time("-2h", now()), Ok(at(2023, 5, 10, 13, 30, 0)));
+        assert_eq!(parse_time("+1d", now()), Ok(at(2023, 5, 11, 15, 30, 0)));
+        assert_eq!(
+            parse_time("1h 30min ago", now()),
+            Ok(at(2023, 5, 10, 14, 0, 0))
+        );
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        assert!(parse_time("soon", now()).is_err());
+        assert!(parse_time("-2 fortnights", now()).is_err());
+        assert!(parse_time("-", now()).is_err());
+        assert!(parse_time("2h", now()).is_err());
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("15min"), Some(Duration::minutes(15)));
+        assert_eq!(parse_duration("1d 12h"), Some(Duration::hours(36)));
+        assert_eq!(parse_duration("hour"), None);
+        assert_eq!(parse_duration(""), None);
+    }
+}
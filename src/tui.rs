@@ -0,0 +1,583 @@
+//! Interactive terminal UI over the statistics of a parsed journal.
+//!
+//! Every view is computed from the message frequency map of `JournalStat`,
+//! so the priority filter applies to all of them and the largest messages are
+//! the largest distinct messages, not only as many as `--large-messages`.
+
+use crate::{priority, JournalStat, Message};
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::{Constraint, Layout, Rect},
+    style::{Modifier, Style},
+    text::Line,
+    widgets::{Block, Borders, Cell, Clear, Paragraph, Row, Table, TableState, Wrap},
+    DefaultTerminal, Frame,
+};
+use std::{cmp::Ordering, collections::HashMap, io};
+
+/// Rows moved by PageUp and PageDown.
+const PAGE: usize = 20;
+
+/// Longest message shown in a table cell, the rest is left to the popup.
+const MESSAGE_WIDTH: usize = 200;
+
+/// The tables that can be browsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum View {
+    Processes,
+    Messages,
+    Largest,
+}
+
+impl View {
+    const ALL: [View; 3] = [View::Processes, View::Messages, View::Largest];
+
+    fn title(self) -> &'static str {
+        match self {
+            View::Processes => "Processes",
+            View::Messages => "Top talkers",
+            View::Largest => "Largest",
+        }
+    }
+
+    fn columns(self) -> &'static [Column] {
+        match self {
+            View::Processes => &[Column::Process, Column::Count, Column::Distinct],
+            View::Messages => &[
+                Column::Count,
+                Column::Process,
+                Column::Priority,
+                Column::Message,
+            ],
+            View::Largest => &[
+                Column::Size,
+                Column::Count,
+                Column::Process,
+                Column::Priority,
+                Column::Message,
+            ],
+        }
+    }
+
+    /// Column sorted on until another is picked, largest first.
+    fn default_sort(self) -> usize {
+        match self {
+            View::Processes => 1,
+            View::Messages => 0,
+            View::Largest => 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Column {
+    Process,
+    Count,
+    Distinct,
+    Size,
+    Priority,
+    Message,
+}
+
+impl Column {
+    fn title(self) -> &'static str {
+        match self {
+            Column::Process => "Process",
+            Column::Count => "Messages",
+            Column::Distinct => "Distinct",
+            Column::Size => "Size",
+            Column::Priority => "Priority",
+            Column::Message => "Message",
+        }
+    }
+
+    fn width(self) -> Constraint {
+        match self {
+            Column::Process => Constraint::Length(24),
+            Column::Message => Constraint::Fill(1),
+            _ => Constraint::Length(10),
+        }
+    }
+}
+
+/// A process and what it logged within the priority filter.
+#[derive(Debug, PartialEq, Eq)]
+struct ProcessRow<'a> {
+    process: &'a str,
+    count: u64,
+    distinct: usize,
+}
+
+impl ProcessRow<'_> {
+    fn cmp_by(&self, other: &Self, column: Column) -> Ordering {
+        match column {
+            Column::Count => self.count.cmp(&other.count),
+            Column::Distinct => self.distinct.cmp(&other.distinct),
+            _ => self.process.cmp(other.process),
+        }
+    }
+}
+
+/// A distinct message and how often it was logged.
+#[derive(Debug, PartialEq, Eq)]
+struct MessageRow<'a> {
+    message: &'a Message,
+    count: u32,
+}
+
+impl MessageRow<'_> {
+    fn cmp_by(&self, other: &Self, column: Column) -> Ordering {
+        let (a, b) = (self.message, other.message);
+        match column {
+            Column::Count => self.count.cmp(&other.count),
+            Column::Size => a.msg.len().cmp(&b.msg.len()),
+            Column::Process => a.process.cmp(&b.process),
+            Column::Priority => a.priority.cmp(&b.priority),
+            // The message first, then its process and priority.
+            _ => a.cmp(b),
+        }
+    }
+}
+
+/// What is shown and how, independent of the terminal.
+struct App<'a> {
+    stat: &'a JournalStat,
+    view: View,
+    // Process the top talkers are narrowed to.
+    process: Option<String>,
+    // Priority levels shown, indexed by level.
+    priorities: [bool; 8],
+    // Sort column and whether it is ascending, per view.
+    sort: [(usize, bool); 3],
+    table: TableState,
+    // Full text of the message opened.
+    popup: Option<String>,
+    quit: bool,
+}
+
+impl<'a> App<'a> {
+    fn new(stat: &'a JournalStat) -> Self {
+        Self {
+            stat,
+            view: View::Processes,
+            process: None,
+            priorities: [true; 8],
+            sort: View::ALL.map(|view| (view.default_sort(), false)),
+            table: TableState::default().with_selected(0),
+            popup: None,
+            quit: false,
+        }
+    }
+
+    fn view_index(&self) -> usize {
+        View::ALL.iter().position(|v| *v == self.view).unwrap()
+    }
+
+    /// Whether a message passes the priority filter. Priorities outside the
+    /// syslog levels are always shown.
+    fn shown(&self, message: &Message) -> bool {
+        message
+            .priority
+            .parse::<usize>()
+            .ok()
+            .and_then(|level| self.priorities.get(level))
+            .is_none_or(|shown| *shown)
+    }
+
+    fn sort_by<T>(&self, rows: &mut [T], cmp: impl Fn(&T, &T, Column) -> Ordering) {
+        let (column, ascending) = self.sort[self.view_index()];
+        let column = self.view.columns()[column];
+        rows.sort_by(|a, b| {
+            let order = cmp(a, b, column);
+            // Ties keep a stable order whatever the direction.
+            let order = if ascending { order } else { order.reverse() };
+            order.then_with(|| cmp(a, b, Column::Message))
+        });
+    }
+
+    fn process_rows(&self) -> Vec<ProcessRow<'a>> {
+        let mut per_process: HashMap<&str, (u64, usize)> = HashMap::new();
+        for (message, count) in self.stat.message_counts() {
+            if self.shown(message) {
+                let row = per_process.entry(&message.process).or_default();
+                row.0 += *count as u64;
+                row.1 += 1;
+            }
+        }
+
+        let mut rows: Vec<ProcessRow> = per_process
+            .into_iter()
+            .map(|(process, (count, distinct))| ProcessRow {
+                process,
+                count,
+                distinct,
+            })
+            .collect();
+        self.sort_by(&mut rows, |a, b, column| a.cmp_by(b, column));
+        rows
+    }
+
+    fn message_rows(&self) -> Vec<MessageRow<'a>> {
+        let process = match self.view {
+            View::Messages => self.process.as_deref(),
+            _ => None,
+        };
+
+        let mut rows: Vec<MessageRow> = self
+            .stat
+            .message_counts()
+            .iter()
+            .filter(|(message, _)| self.shown(message))
+            .filter(|(message, _)| process.is_none_or(|p| message.process == p))
+            .map(|(message, count)| MessageRow {
+                message,
+                count: *count,
+            })
+            .collect();
+        self.sort_by(&mut rows, |a, b, column| a.cmp_by(b, column));
+        rows
+    }
+
+    fn len(&self) -> usize {
+        match self.view {
+            View::Processes => self.process_rows().len(),
+            _ => self.message_rows().len(),
+        }
+    }
+
+    fn switch(&mut self, view: View) {
+        self.view = view;
+        self.table.select(Some(0));
+    }
+
+    fn select(&mut self, index: usize) {
+        let last = self.len().saturating_sub(1);
+        self.table.select(Some(index.min(last)));
+    }
+
+    fn selected(&self) -> usize {
+        self.table.selected().unwrap_or(0)
+    }
+
+    /// Act on a key press.
+    fn handle(&mut self, key: KeyCode) {
+        if self.popup.is_some() {
+            if matches!(key, KeyCode::Esc | KeyCode::Enter | KeyCode::Char('q')) {
+                self.popup = None;
+            }
+            return;
+        }
+
+        match key {
+            KeyCode::Char('q') => self.quit = true,
+            KeyCode::Tab => {
+                let next = (self.view_index() + 1) % View::ALL.len();
+                self.switch(View::ALL[next]);
+            }
+            KeyCode::BackTab => {
+                let prev = (self.view_index() + View::ALL.len() - 1) % View::ALL.len();
+                self.switch(View::ALL[prev]);
+            }
+            KeyCode::Down | KeyCode::Char('j') => self.select(self.selected() + 1),
+            KeyCode::Up | KeyCode::Char('k') => self.select(self.selected().saturating_sub(1)),
+            KeyCode::PageDown => self.select(self.selected() + PAGE),
+            KeyCode::PageUp => self.select(self.selected().saturating_sub(PAGE)),
+            KeyCode::Home => self.select(0),
+            KeyCode::End => self.select(usize::MAX),
+            KeyCode::Char('s') => {
+                let columns = self.view.columns().len();
+                let sort = &mut self.sort[self.view_index()];
+                *sort = ((sort.0 + 1) % columns, false);
+            }
+            KeyCode::Char('r') => {
+                let sort = &mut self.sort[self.view_index()];
+                sort.1 = !sort.1;
+            }
+            KeyCode::Char(c @ '0'..='7') => {
+                let level = c as usize - '0' as usize;
+                self.priorities[level] = !self.priorities[level];
+                self.select(self.selected());
+            }
+            KeyCode::Char('a') => {
+                self.priorities = [true; 8];
+            }
+            KeyCode::Enter => match self.view {
+                View::Processes => {
+                    if let Some(row) = self.process_rows().get(self.selected()) {
+                        self.process = Some(row.process.to_string());
+                        self.switch(View::Messages);
+                    }
+                }
+                _ => {
+                    self.popup = self
+                        .message_rows()
+                        .get(self.selected())
+                        .map(|row| row.message.msg.clone());
+                }
+            },
+            KeyCode::Esc | KeyCode::Backspace
+                if self.view == View::Messages && self.process.is_some() =>
+            {
+                self.process = None;
+                self.switch(View::Processes);
+            }
+            _ => {}
+        }
+    }
+
+    fn cells(&self, view: View) -> Vec<Vec<String>> {
+        let priority_name = |p: &str| priority::name(p).unwrap_or(p).to_string();
+
+        match view {
+            View::Processes => self
+                .process_rows()
+                .iter()
+                .map(|row| {
+                    vec![
+                        row.process.to_string(),
+                        row.count.to_string(),
+                        row.distinct.to_string(),
+                    ]
+                })
+                .collect(),
+            _ => self
+                .message_rows()
+                .iter()
+                .map(|row| {
+                    view.columns()
+                        .iter()
+                        .map(|column| match column {
+                            Column::Count => row.count.to_string(),
+                            Column::Size => row.message.msg.len().to_string(),
+                            Column::Process => row.message.process.clone(),
+                            Column::Priority => priority_name(&row.message.priority),
+                            _ => row
+                                .message
+                                .msg
+                                .chars()
+                                .take(MESSAGE_WIDTH)
+                                .map(|c| if c.is_control() { ' ' } else { c })
+                                .collect(),
+                        })
+                        .collect()
+                })
+                .collect(),
+        }
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [header, body, footer] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Fill(1),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        let tabs: Vec<String> = View::ALL
+            .iter()
+            .map(|view| {
+                if *view == self.view {
+                    format!("[{}]", view.title())
+                } else {
+                    format!(" {} ", view.title())
+                }
+            })
+            .collect();
+        let levels: String = self
+            .priorities
+            .iter()
+            .enumerate()
+            .map(|(level, shown)| {
+                if *shown {
+                    (b'0' + level as u8) as char
+                } else {
+                    '-'
+                }
+            })
+            .collect();
+        frame.render_widget(
+            Line::from(format!(
+                "{}   priorities {}   {} messages",
+                tabs.join(" "),
+                levels,
+                self.stat.total_messages()
+            )),
+            header,
+        );
+
+        let (column, ascending) = self.sort[self.view_index()];
+        let columns = self.view.columns();
+        let titles = columns.iter().enumerate().map(|(i, c)| {
+            let arrow = match (i == column, ascending) {
+                (false, _) => "",
+                (true, true) => " ▲",
+                (true, false) => " ▼",
+            };
+            Cell::from(format!("{}{}", c.title(), arrow))
+        });
+        let rows = self.cells(self.view).into_iter().map(Row::new);
+
+        let mut title = self.view.title().to_string();
+        if let (View::Messages, Some(process)) = (self.view, &self.process) {
+            title = format!("{} of {}", title, process);
+        }
+        let table = Table::new(rows, columns.iter().map(|c| c.width()))
+            .header(Row::new(titles).style(Style::new().add_modifier(Modifier::BOLD)))
+            .block(Block::new().borders(Borders::ALL).title(title))
+            .row_highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(table, body, &mut self.table);
+
+        frame.render_widget(
+            Line::from(
+                "q quit  tab view  enter open  esc back  s sort  r reverse  0-7 priority  a all",
+            ),
+            footer,
+        );
+
+        if let Some(text) = &self.popup {
+            let area = popup_area(frame.area());
+            frame.render_widget(Clear, area);
+            frame.render_widget(
+                Paragraph::new(text.as_str())
+                    .wrap(Wrap { trim: false })
+                    .block(Block::new().borders(Borders::ALL).title("Message")),
+                area,
+            );
+        }
+    }
+}
+
+/// The middle of the screen, leaving a margin around it.
+fn popup_area(area: Rect) -> Rect {
+    let [_, middle, _] = Layout::vertical([
+        Constraint::Percentage(15),
+        Constraint::Percentage(70),
+        Constraint::Percentage(15),
+    ])
+    .areas(area);
+    let [_, middle, _] = Layout::horizontal([
+        Constraint::Percentage(10),
+        Constraint::Percentage(80),
+        Constraint::Percentage(10),
+    ])
+    .areas(middle);
+    middle
+}
+
+fn event_loop(terminal: &mut DefaultTerminal, app: &mut App) -> io::Result<()> {
+    while !app.quit {
+        terminal.draw(|frame| app.draw(frame))?;
+        if let Event::Key(key) = event::read()? {
+            if key.kind == KeyEventKind::Press {
+                app.handle(key.code);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Browse the statistics gathered by `stat` until the user quits.
+pub fn run(stat: &JournalStat) -> io::Result<()> {
+    let mut terminal = ratatui::init();
+    let result = event_loop(&mut terminal, &mut App::new(stat));
+    ratatui::restore();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::Entry;
+    use std::path::Path;
+
+    fn stat() -> JournalStat {
+        let entries: Vec<Entry> = [
+            ("a long message from app", "app", "6"),
+            ("a long message from app", "app", "6"),
+            ("err", "app", "3"),
+            ("tick", "cron", "6"),
+            ("tick", "cron", "6"),
+            ("tick", "cron", "6"),
+        ]
+        .iter()
+        .map(|(msg, process, priority)| {
+            [("MESSAGE", msg), ("_COMM", process), ("PRIORITY", priority)]
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        })
+        .collect();
+
+        let mut stat = JournalStat::new(Path::new("test"), Box::new(entries.into_iter()));
+        stat.parse().unwrap();
+        stat
+    }
+
+    fn processes(app: &App) -> Vec<(String, u64)> {
+        app.process_rows()
+            .iter()
+            .map(|row| (row.process.to_string(), row.count))
+            .collect()
+    }
+
+    #[test]
+    fn sorts_and_filters_processes() {
+        let stat = stat();
+        let mut app = App::new(&stat);
+        assert_eq!(processes(&app), [("app".into(), 3), ("cron".into(), 3)]);
+
+        // Sort by distinct messages, then reverse it.
+        app.handle(KeyCode::Char('s'));
+        assert_eq!(app.process_rows()[0].process, "app");
+        app.handle(KeyCode::Char('r'));
+        assert_eq!(app.process_rows()[0].process, "cron");
+
+        app.handle(KeyCode::Char('6'));
+        assert_eq!(processes(&app), [("app".into(), 1)]);
+        app.handle(KeyCode::Char('a'));
+        assert_eq!(app.process_rows().len(), 2);
+
+        let mut terminal =
+            ratatui::Terminal::new(ratatui::backend::TestBackend::new(80, 10)).unwrap();
+        terminal.draw(|frame| app.draw(frame)).unwrap();
+        let screen: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect();
+        assert!(screen.contains("[Processes]"));
+        assert!(screen.contains("Distinct ▲"));
+    }
+
+    #[test]
+    fn drills_into_a_process_and_opens_messages() {
+        let stat = stat();
+        let mut app = App::new(&stat);
+
+        app.handle(KeyCode::Enter);
+        assert_eq!(app.view, View::Messages);
+        assert_eq!(app.process.as_deref(), Some("app"));
+        let messages: Vec<&str> = app
+            .message_rows()
+            .iter()
+            .map(|row| row.message.msg.as_str())
+            .collect();
+        assert_eq!(messages, ["a long message from app", "err"]);
+
+        app.handle(KeyCode::Down);
+        app.handle(KeyCode::Enter);
+        assert_eq!(app.popup.as_deref(), Some("err"));
+        app.handle(KeyCode::Esc);
+        assert!(app.popup.is_none());
+
+        app.handle(KeyCode::Esc);
+        assert_eq!((app.view, app.process.as_deref()), (View::Processes, None));
+
+        app.handle(KeyCode::BackTab);
+        assert_eq!(app.view, View::Largest);
+        assert_eq!(app.message_rows()[0].message.msg, "a long message from app");
+    }
+}